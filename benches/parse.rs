@@ -0,0 +1,86 @@
+//! Parser benchmarks
+//!
+//! The corpus is every desktop file in `/usr/share/applications`, or the
+//! directory in `$DOPEN_BENCH_CORPUS`. Synthetic files cover comment heavy
+//! and heavily translated entries.
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::hint::black_box;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use libdopen::parse;
+
+fn corpus() -> Vec<Vec<u8>> {
+    let dir = env::var_os("DOPEN_BENCH_CORPUS")
+        .map_or_else(|| PathBuf::from("/usr/share/applications"), PathBuf::from);
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "desktop"))
+        .filter_map(|p| fs::read(p).ok())
+        .collect()
+}
+
+/// An entry with a comment and a blank line between every key
+fn commented_entry() -> String {
+    let mut input = String::from("[Desktop Entry]\nType=Application\nName=App\n");
+    for n in 0..500 {
+        writeln!(input, "# comment number {}, explaining the next key\n", n).unwrap();
+        writeln!(input, "X-Key-{}=value {}", n, n).unwrap();
+    }
+    input
+}
+
+/// An entry with hundreds of translations of its localized keys
+fn translated_entry() -> String {
+    let mut input = String::from("[Desktop Entry]\nType=Application\nName=App\nExec=app %U\n");
+    for key in ["Name", "GenericName", "Comment", "Keywords"] {
+        for n in 0..200u8 {
+            let lang = [b'a' + n / 26, b'a' + n % 26];
+            let locale = format!("{}_C{}", String::from_utf8_lossy(&lang), n % 7);
+            writeln!(
+                input,
+                "{}[{}]=A translated {} for locale {}",
+                key, locale, key, n
+            )
+            .unwrap();
+        }
+    }
+    input
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let corpus = corpus();
+    if !corpus.is_empty() {
+        let mut group = c.benchmark_group("corpus");
+        group.throughput(Throughput::Bytes(
+            corpus.iter().map(|f| f.len() as u64).sum(),
+        ));
+        group.bench_function("parse", |b| {
+            b.iter(|| {
+                for file in &corpus {
+                    let _ = black_box(parse(black_box(file)));
+                }
+            })
+        });
+        group.finish();
+    }
+
+    for (name, input) in [
+        ("commented", commented_entry()),
+        ("translated", translated_entry()),
+    ] {
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_function("parse", |b| b.iter(|| parse(black_box(&input)).unwrap()));
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
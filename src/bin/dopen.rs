@@ -0,0 +1,395 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use clap::{Arg, Command};
+use libdopen::entries::{Comment, Exec, GenericName, Hidden, Name, NoDisplay};
+use libdopen::execute::{self, EntrySource, ExecContext, Launchers};
+use libdopen::xdg::BaseDirs;
+use libdopen::{current_locale, parse, parse_file, DesktopEntry, Severity, SPEC_VERSION};
+
+fn main() {
+    let matches = Command::new("dopen")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Execute a .desktop file")
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .subcommand(
+            Command::new("conformance")
+                .about("Check the spec examples and list the optional behaviors built in"),
+        )
+        .subcommand(
+            Command::new("show")
+                .about("Print the name, comment and actions of a .desktop file")
+                .arg(Arg::new("file").required(true)),
+        )
+        .subcommand(
+            Command::new("search")
+                .about("List installed applications matching a query")
+                .arg(Arg::new("query").required(true).multiple_values(true)),
+        )
+        .arg(
+            Arg::new("action")
+                .long("action")
+                .short('a')
+                .takes_value(true)
+                .help("Run one of the entry's actions instead of the main command"),
+        )
+        .arg(
+            Arg::new("locale")
+                .long("locale")
+                .takes_value(true)
+                .global(true)
+                .help("Locale for names, search and the command's %c and %i, instead of LANG"),
+        )
+        .arg(
+            Arg::new("file")
+                .required(true)
+                .help("The desktop file to execute"),
+        )
+        .arg(
+            Arg::new("args")
+                .multiple_values(true)
+                .help("Files or urls to pass to the command"),
+        )
+        .get_matches();
+
+    let locale = matches
+        .value_of("locale")
+        .map(String::from)
+        .or_else(current_locale);
+    let locale = locale.as_deref();
+    match matches.subcommand() {
+        Some(("conformance", _)) => conformance(),
+        Some(("show", sub)) => show(sub.value_of("file").unwrap(), locale),
+        Some(("search", sub)) => {
+            let query: Vec<_> = sub.values_of("query").unwrap().collect();
+            search(&query.join(" "), locale)
+        }
+        _ => {}
+    }
+    let path = matches.value_of("file").unwrap();
+    let args: Vec<String> = matches
+        .values_of("args")
+        .map_or_else(Vec::new, |a| a.map(String::from).collect());
+    let entry = load(path);
+    let context = ExecContext::with_locale(&entry, &args, EntrySource::Path(path.into()), locale);
+    let result = match matches.value_of("action") {
+        Some(id) => run_action(&entry, id, &context, path),
+        None => Launchers::new().launch(&entry, &context),
+    };
+    // executing only returns if it failed
+    if let Err(e) = result {
+        fail(format_args!("{}: {}", path, e));
+    }
+}
+
+fn load(path: &str) -> DesktopEntry {
+    match parse_file(path) {
+        Ok(entry) => entry,
+        Err(e) => fail(format_args!("{}: {}", path, e)),
+    }
+}
+
+/// Print the localized name, generic name, comment and actions of an entry
+fn show(path: &str, locale: Option<&str>) -> ! {
+    let entry = load(path);
+    let Some(main) = entry.main_group() else {
+        fail(format_args!("{}: no [Desktop Entry] group", path));
+    };
+    if let Some(Name(name)) = main.get_for_locale(locale) {
+        println!("Name: {}", name);
+    }
+    if let Some(GenericName(name)) = main.get_for_locale(locale) {
+        println!("GenericName: {}", name);
+    }
+    if let Some(Comment(comment)) = main.get_for_locale(locale) {
+        println!("Comment: {}", comment);
+    }
+    let actions = entry.localized_actions(locale);
+    if !actions.is_empty() {
+        println!("Actions:");
+        for action in actions {
+            let Name(name) = action.name();
+            println!("  {}: {}", action.id(), name);
+        }
+    }
+    process::exit(0)
+}
+
+/// Print the desktop file ID and name of each installed application
+/// matching `query`
+fn search(query: &str, locale: Option<&str>) -> ! {
+    let mut seen = HashSet::new();
+    for dir in BaseDirs::from_env().applications_dirs() {
+        let mut files = Vec::new();
+        desktop_files(&dir, &mut files);
+        files.sort();
+        for file in files {
+            let Some(id) = desktop_id(&dir, &file) else {
+                continue;
+            };
+            // earlier directories take precedence, even for hidden entries
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            let Ok(entry) = parse_file(&file) else {
+                continue;
+            };
+            let hidden = entry.get::<Hidden>() == Some(Hidden(true))
+                || entry.get::<NoDisplay>() == Some(NoDisplay(true));
+            if hidden || !entry.matches(query, locale) {
+                continue;
+            }
+            let name = entry
+                .main_group()
+                .and_then(|main| main.get_for_locale(locale))
+                .map_or_else(String::new, |Name(n)| n);
+            println!("{}: {}", id, name);
+        }
+    }
+    process::exit(0)
+}
+
+/// Collect the `.desktop` files in `dir` and its subdirectories
+fn desktop_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            desktop_files(&path, files);
+        } else if path.extension().is_some_and(|e| e == "desktop") {
+            files.push(path);
+        }
+    }
+}
+
+/// The desktop file ID of `file` in the applications directory `dir`
+fn desktop_id(dir: &Path, file: &Path) -> Option<String> {
+    let relative = file.strip_prefix(dir).ok()?.to_str()?;
+    Some(relative.replace('/', "-"))
+}
+
+fn run_action(
+    entry: &DesktopEntry,
+    id: &str,
+    context: &ExecContext,
+    path: &str,
+) -> Result<(), execute::Error> {
+    let action = match entry.action(id) {
+        Some(action) => action,
+        None => fail(format_args!("{}: no action named {}", path, id)),
+    };
+    action.launch(context)
+}
+
+/// Optional behaviors and the cargo feature that enables each
+const BEHAVIORS: &[(&str, bool)] = &[
+    (
+        "Legacy-Mixed encoding (encoding)",
+        cfg!(feature = "encoding"),
+    ),
+    ("D-Bus activation (dbus)", cfg!(feature = "dbus")),
+    ("mailcap handlers (mailcap)", cfg!(feature = "mailcap")),
+    ("serde support (serde)", cfg!(feature = "serde")),
+    ("tracing events (tracing)", cfg!(feature = "tracing")),
+];
+
+/// The example from the spec
+const SPEC_EXAMPLE: &str = "\
+[Desktop Entry]
+Version=1.0
+Type=Application
+Name=Foo Viewer
+Comment=The best viewer for Foo objects available!
+TryExec=fooview
+Exec=fooview %F
+Icon=fooview
+MimeType=image/x-foo;
+Actions=Gallery;Create;
+
+[Desktop Action Gallery]
+Exec=fooview --gallery
+Name=Browse Gallery
+
+[Desktop Action Create]
+Exec=fooview --create-new
+Name=Create a new Foo!
+Icon=fooview-new
+";
+
+/// An example run by `dopen conformance`
+type Check = fn() -> Outcome;
+
+enum Outcome {
+    Pass,
+    Fail(String),
+    Skipped(&'static str),
+}
+
+/// Run the bundled examples and print which optional behaviors are enabled
+///
+/// Exits with status 1 if any example fails.
+fn conformance() -> ! {
+    println!("Desktop Entry Specification {}", SPEC_VERSION);
+    println!();
+    println!("Optional behaviors:");
+    for &(name, enabled) in BEHAVIORS {
+        let state = if enabled { "enabled" } else { "disabled" };
+        println!("  {:<36} {}", name, state);
+    }
+    println!();
+    println!("Examples:");
+    let examples: &[(&str, Check)] = &[
+        ("spec example file", check_spec_example),
+        ("localized keys", check_localized),
+        ("escape sequences", check_escapes),
+        ("field codes", check_field_codes),
+        ("quoted arguments", check_quoting),
+        ("Legacy-Mixed encoding", check_legacy_encoding),
+        ("D-Bus activation", check_dbus),
+    ];
+    let mut failed = false;
+    for &(name, check) in examples {
+        let result = match check() {
+            Outcome::Pass => "ok".to_string(),
+            Outcome::Fail(why) => {
+                failed = true;
+                format!("FAILED: {}", why)
+            }
+            Outcome::Skipped(why) => format!("skipped, {}", why),
+        };
+        println!("  {:<36} {}", name, result);
+    }
+    process::exit(failed as i32)
+}
+
+fn check_spec_example() -> Outcome {
+    let entry = match parse(SPEC_EXAMPLE) {
+        Ok(entry) => entry,
+        Err(e) => return Outcome::Fail(e.to_string()),
+    };
+    let diagnostics = entry.validate(SPEC_VERSION);
+    if let Some(error) = diagnostics.iter().find(|d| d.severity == Severity::Error) {
+        return Outcome::Fail(error.to_string());
+    }
+    let ids: Vec<_> = entry.actions().iter().map(|a| a.id().to_string()).collect();
+    if ids == ["Gallery", "Create"] {
+        Outcome::Pass
+    } else {
+        Outcome::Fail(format!("got actions {:?}", ids))
+    }
+}
+
+fn check_localized() -> Outcome {
+    let entry = match parse("[Desktop Entry]\nName=Colour\nName[en_US]=Color\nName[sr@Latn]=Boja\n")
+    {
+        Ok(entry) => entry,
+        Err(e) => return Outcome::Fail(e.to_string()),
+    };
+    let group = &entry["Desktop Entry"];
+    let name = |locale| group.get_raw_localized("Name", locale);
+    expect(&[
+        (name("en_US.UTF-8"), Some("Color")),
+        (name("sr_RS@Latn"), Some("Boja")),
+        (name("de_DE"), Some("Colour")),
+    ])
+}
+
+fn check_escapes() -> Outcome {
+    let entry = match parse("[Desktop Entry]\nComment=a\\sb\\tc\\nd\\\\e\n") {
+        Ok(entry) => entry,
+        Err(e) => return Outcome::Fail(e.to_string()),
+    };
+    let comment = entry.get::<Comment>();
+    expect(&[(comment.as_deref(), Some("a b\tc\nd\\e"))])
+}
+
+fn check_field_codes() -> Outcome {
+    let input = "[Desktop Entry]\nName=Foo\nIcon=foo\nExec=foo %i --name=%c %k %F %%\n";
+    let entry = match parse(input) {
+        Ok(entry) => entry,
+        Err(e) => return Outcome::Fail(e.to_string()),
+    };
+    let args = ["/tmp/a".to_string(), "/tmp/b".to_string()];
+    let source = EntrySource::Path("/usr/share/applications/foo.desktop".into());
+    let context = ExecContext::with_locale(&entry, &args, source, None);
+    let Some(exec) = entry.get::<Exec>() else {
+        return Outcome::Fail("missing Exec".into());
+    };
+    let command = match execute::parse_command(&exec, &context) {
+        Ok(command) => command,
+        Err(e) => return Outcome::Fail(e.to_string()),
+    };
+    let actual: Vec<_> = command.get_args().map(|a| a.to_string_lossy()).collect();
+    let expected = [
+        "--icon",
+        "foo",
+        "--name=Foo",
+        "/usr/share/applications/foo.desktop",
+        "/tmp/a",
+        "/tmp/b",
+        "%",
+    ];
+    if actual == expected {
+        Outcome::Pass
+    } else {
+        Outcome::Fail(format!("got {:?}", actual))
+    }
+}
+
+fn check_quoting() -> Outcome {
+    match execute::tokenize(r#""/opt/foo bar/foo" "say \"hi\"" plain"#) {
+        Ok(words) if words == ["/opt/foo bar/foo", "say \"hi\"", "plain"] => Outcome::Pass,
+        Ok(words) => Outcome::Fail(format!("got {:?}", words)),
+        Err(e) => Outcome::Fail(e.to_string()),
+    }
+}
+
+fn check_legacy_encoding() -> Outcome {
+    if !cfg!(feature = "encoding") {
+        return Outcome::Skipped("built without the encoding feature");
+    }
+    match parse(&b"[Desktop Entry]\nEncoding=Legacy-Mixed\nName=Caf\xe9\n"[..]) {
+        Ok(entry) => expect(&[(entry["Desktop Entry"].get_raw("Name"), Some("Caf\u{e9}"))]),
+        Err(e) => Outcome::Fail(e.to_string()),
+    }
+}
+
+#[cfg(feature = "dbus")]
+fn check_dbus() -> Outcome {
+    let input = "[Desktop Entry]\nName=Foo\nDBusActivatable=true\n";
+    let entry = match parse(input) {
+        Ok(entry) => entry,
+        Err(e) => return Outcome::Fail(e.to_string()),
+    };
+    let source = EntrySource::Path("/usr/share/applications/org.example.Foo.desktop".into());
+    let context = ExecContext::new(&entry, &[], source);
+    match Launchers::new().prepare(&entry, &context) {
+        Ok(execute::Launch::DBus(_)) => Outcome::Pass,
+        Ok(launch) => Outcome::Fail(format!("launched with {:?}", launch)),
+        Err(e) => Outcome::Fail(e.to_string()),
+    }
+}
+
+#[cfg(not(feature = "dbus"))]
+fn check_dbus() -> Outcome {
+    Outcome::Skipped("built without the dbus feature")
+}
+
+fn expect(pairs: &[(Option<&str>, Option<&str>)]) -> Outcome {
+    match pairs.iter().find(|(actual, expected)| actual != expected) {
+        Some((actual, expected)) => {
+            Outcome::Fail(format!("got {:?}, expected {:?}", actual, expected))
+        }
+        None => Outcome::Pass,
+    }
+}
+
+fn fail(msg: std::fmt::Arguments) -> ! {
+    eprintln!("dopen: {}", msg);
+    process::exit(1)
+}
@@ -0,0 +1,192 @@
+use super::entries::{Actions, DBusActivatable, Exec, Icon, Name};
+use super::execute::{self, CommandExecutor, ExecContext, Executor};
+use super::model::{current_locale, DesktopEntry, Group};
+
+/// An additional action of an application, from a "Desktop Action" group
+#[derive(Debug, Clone, PartialEq)]
+pub struct Action {
+    id: String,
+    name: Name,
+    icon: Option<Icon>,
+    exec: Option<Exec>,
+    /// Whether the entry the action belongs to is `DBusActivatable`
+    dbus_activatable: bool,
+}
+
+impl Action {
+    fn from_group(
+        id: &str,
+        group: &Group,
+        entry: &DesktopEntry,
+        locale: Option<&str>,
+    ) -> Option<Action> {
+        Some(Action {
+            id: id.into(),
+            name: group.get_for_locale(locale)?,
+            icon: group.get_for_locale(locale),
+            exec: group.get(),
+            dbus_activatable: entry.get::<DBusActivatable>() == Some(DBusActivatable(true)),
+        })
+    }
+
+    /// The identifier of the action, as listed in the `Actions` key
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The name of the action, localized when the action was looked up
+    pub fn name(&self) -> &Name {
+        &self.name
+    }
+
+    pub fn icon(&self) -> Option<&Icon> {
+        self.icon.as_ref()
+    }
+
+    pub fn exec(&self) -> Option<&Exec> {
+        self.exec.as_ref()
+    }
+
+    /// Run the action's command
+    ///
+    /// `context` should be for the entry the action belongs to. Like
+    /// `execute::execute`, on Unix this only returns if launching failed.
+    ///
+    /// With the `dbus` feature, if the entry is `DBusActivatable` and was
+    /// read from a file, the action is run with `ActivateAction` instead,
+    /// so it doesn't need an `Exec` key. If that call fails, `Exec` is run
+    /// if the action has one.
+    pub fn launch(&self, context: &ExecContext) -> Result<(), execute::Error> {
+        #[cfg(feature = "dbus")]
+        if let Some(executor) = self.dbus_executor(context) {
+            // nothing was started if activation failed, so fall back to Exec
+            match executor.execute() {
+                Err(error) if self.exec.is_none() => return Err(error),
+                Err(execute::Error::DBusFailed(_)) => {}
+                result => return result,
+            }
+        }
+        let exec = self.exec.as_ref().ok_or(execute::Error::NoCommand)?;
+        CommandExecutor::with_context(exec, context)?.execute()
+    }
+
+    #[cfg(feature = "dbus")]
+    fn dbus_executor(&self, context: &ExecContext) -> Option<execute::DBusExecutor> {
+        let id = context.desktop_id().filter(|_| self.dbus_activatable)?;
+        let executor = execute::DBusExecutor::new(&id, &[]).ok()?;
+        Some(executor.action(&self.id))
+    }
+}
+
+impl DesktopEntry {
+    /// Get the actions listed in the `Actions` key, in order
+    ///
+    /// Actions without a matching "Desktop Action" group, or whose group
+    /// has no `Name`, are skipped. Groups not listed in `Actions` are ignored.
+    /// Names and icons are localized for the current locale.
+    pub fn actions(&self) -> Vec<Action> {
+        self.localized_actions(current_locale().as_deref())
+    }
+
+    /// Like `actions`, but localize the names and icons for `locale`
+    ///
+    /// With `None`, the unlocalized values are used.
+    pub fn localized_actions(&self, locale: Option<&str>) -> Vec<Action> {
+        let Some(Actions(ids)) = self.get::<Actions>() else {
+            return Vec::new();
+        };
+        ids.iter()
+            .filter_map(|id| Action::from_group(id, self.action_group(id)?, self, locale))
+            .collect()
+    }
+
+    /// Get a listed action by id
+    pub fn action(&self, id: &str) -> Option<Action> {
+        let Actions(ids) = self.get::<Actions>()?;
+        if !ids.iter().any(|i| i == id) {
+            return None;
+        }
+        let locale = current_locale();
+        Action::from_group(id, self.action_group(id)?, self, locale.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute::EntrySource;
+    use crate::parse;
+
+    #[test]
+    fn actions_test() {
+        let entry = parse(
+            "\
+[Desktop Entry]
+Name=Sample
+Exec=sample %U
+Actions=new;private;missing;
+
+[Desktop Action new]
+Name=New Window
+Name[fr]=Nouvelle fenêtre
+Exec=sample --new
+
+[Desktop Action private]
+Name=Private Window
+Icon=sample-private
+
+[Desktop Action unlisted]
+Name=Unlisted
+Exec=sample --unlisted
+",
+        )
+        .unwrap();
+        let actions = entry.actions();
+        let ids: Vec<&str> = actions.iter().map(Action::id).collect();
+        assert_eq!(ids, ["new", "private"]);
+        assert_eq!(actions[0].exec(), Some(&Exec("sample --new".into())));
+        assert_eq!(actions[1].icon(), Some(&Icon("sample-private".into())));
+        assert_eq!(entry.action("private"), Some(actions[1].clone()));
+        assert_eq!(entry.action("unlisted"), None);
+        let name = |locale| entry.localized_actions(locale)[0].name().clone();
+        assert_eq!(name(Some("fr_FR")), Name("Nouvelle fenêtre".into()));
+        assert_eq!(name(None), Name("New Window".into()));
+
+        let args = [];
+        let context = ExecContext::new(&entry, &args, EntrySource::None);
+        assert!(matches!(
+            actions[1].launch(&context),
+            Err(execute::Error::NoCommand)
+        ));
+    }
+
+    #[cfg(feature = "dbus")]
+    #[test]
+    fn dbus_action_test() {
+        let entry = parse(
+            "\
+[Desktop Entry]
+Name=Sample
+DBusActivatable=true
+Actions=new;
+
+[Desktop Action new]
+Name=New Window
+",
+        )
+        .unwrap();
+        let action = entry.action("new").unwrap();
+        let path = "/usr/share/applications/org.example.Sample.desktop";
+        let context = ExecContext::new(&entry, &[], EntrySource::Path(path.into()));
+        let executor = action.dbus_executor(&context).unwrap();
+        let expected = execute::DBusExecutor::new("org.example.Sample", &[]).unwrap();
+        assert_eq!(executor, expected.action("new"));
+        // the bus name comes from the desktop file ID
+        let context = ExecContext::new(&entry, &[], EntrySource::None);
+        assert!(action.dbus_executor(&context).is_none());
+        assert!(matches!(
+            action.launch(&context),
+            Err(execute::Error::NoCommand)
+        ));
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use super::entries::{Actions, Entry, Exec, Name, SerializeEntry, Version};
+use super::error::BuildError;
+use super::model::{DesktopEntry, Group, DESKTOP_ENTRY_NAME, SPEC_VERSION};
+
+/// Fluent builder for a new `DesktopEntry`
+///
+/// ```
+/// use libdopen::DesktopEntryBuilder;
+/// use libdopen::entries::{Exec, Name, Type};
+///
+/// let entry = DesktopEntryBuilder::new()
+///     .set(Type::Application)
+///     .set(Name("Sample".into()))
+///     .set(Exec("sample %U".into()))
+///     .action("new-window", Name("New Window".into()), Exec("sample --new".into()))
+///     .build()
+///     .unwrap();
+/// assert!(entry.action_group("new-window").is_some());
+/// ```
+#[derive(Debug, Clone)]
+pub struct DesktopEntryBuilder {
+    main: Group,
+    actions: Vec<(String, Group)>,
+}
+
+impl DesktopEntryBuilder {
+    pub fn new() -> DesktopEntryBuilder {
+        DesktopEntryBuilder {
+            main: Group::new(DESKTOP_ENTRY_NAME.into(), HashMap::new()),
+            actions: Vec::new(),
+        }
+    }
+
+    /// Set an entry in the "Desktop Entry" group
+    pub fn set<T: SerializeEntry>(mut self, entry: T) -> DesktopEntryBuilder {
+        self.main.set(&entry);
+        self
+    }
+
+    /// Set a raw (already escaped) value in the "Desktop Entry" group
+    ///
+    /// This is mostly useful for localized keys such as `Name[de]`.
+    pub fn set_raw(mut self, key: &str, value: String) -> DesktopEntryBuilder {
+        self.main.set_raw(key, value);
+        self
+    }
+
+    /// Add an action, which is also added to the `Actions` key
+    pub fn action(mut self, id: &str, name: Name, exec: Exec) -> DesktopEntryBuilder {
+        let mut group = Group::new(format!("Desktop Action {}", id), HashMap::new());
+        group.set(&name);
+        group.set(&exec);
+        self.actions.push((id.into(), group));
+        self
+    }
+
+    /// Check the required keys and build the entry
+    ///
+    /// See `DesktopEntry::check_required_keys`. `Version` defaults to
+    /// `SPEC_VERSION`.
+    pub fn build(mut self) -> Result<DesktopEntry, BuildError> {
+        self.main.check_required_keys()?;
+        if self.main.get_raw(Version::name()).is_none() {
+            self.main.set(&Version(SPEC_VERSION.into()));
+        }
+        if !self.actions.is_empty() {
+            let ids = self.actions.iter().map(|(id, _)| id.clone()).collect();
+            self.main.set(&Actions(ids));
+        }
+        let mut groups = vec![self.main];
+        groups.extend(self.actions.into_iter().map(|(_, group)| group));
+        Ok(DesktopEntry::new(groups))
+    }
+}
+
+impl Default for DesktopEntryBuilder {
+    fn default() -> DesktopEntryBuilder {
+        DesktopEntryBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::{DBusActivatable, Type, URL};
+
+    #[test]
+    fn build_test() {
+        let entry = DesktopEntryBuilder::new()
+            .set(Type::Application)
+            .set(Name("Sample".into()))
+            .set_raw("Name[de]", "Beispiel".into())
+            .set(Exec("sample %U".into()))
+            .action("new", Name("New".into()), Exec("sample --new".into()))
+            .build()
+            .unwrap();
+        assert_eq!(
+            entry.to_sorted_string(),
+            "\
+[Desktop Entry]
+Actions=new;
+Exec=sample %U
+Name=Sample
+Name[de]=Beispiel
+Type=Application
+Version=1.5
+
+[Desktop Action new]
+Exec=sample --new
+Name=New
+"
+        );
+    }
+
+    #[test]
+    fn build_errors_test() {
+        let app = DesktopEntryBuilder::new().set(Type::Application);
+        assert_eq!(app.clone().build(), Err(BuildError::MissingKey("Name")));
+        let app = app.set(Name("Sample".into()));
+        assert_eq!(app.clone().build(), Err(BuildError::MissingKey("Exec")));
+        assert!(app.clone().set(DBusActivatable(true)).build().is_ok());
+        assert_eq!(
+            app.set(URL("https://example.com".into())).build(),
+            Err(BuildError::UnexpectedKey {
+                key: "URL",
+                ty: "Application".into()
+            })
+        );
+
+        let link = DesktopEntryBuilder::new()
+            .set(Type::Link)
+            .set(Name("Example".into()));
+        assert_eq!(link.clone().build(), Err(BuildError::MissingKey("URL")));
+        assert!(link.set(URL("https://example.com".into())).build().is_ok());
+        assert_eq!(
+            DesktopEntryBuilder::new().build(),
+            Err(BuildError::MissingKey("Type"))
+        );
+    }
+}
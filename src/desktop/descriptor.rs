@@ -0,0 +1,232 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use super::entries::util;
+use super::model::{current_locale, Group};
+
+/// The type of a value, as listed in the "Possible value types" section of the spec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+    String,
+    LocaleString,
+    Strings,
+    LocaleStrings,
+    Boolean,
+    Numeric,
+}
+
+impl ValueType {
+    /// Check if keys of this type can have translations such as `Name[de]`
+    pub fn is_localized(self) -> bool {
+        matches!(self, ValueType::LocaleString | ValueType::LocaleStrings)
+    }
+}
+
+/// A value read with an `EntryDescriptor`
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynValue {
+    String(String),
+    Strings(Vec<String>),
+    Boolean(bool),
+    Numeric(f64),
+}
+
+impl DynValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            DynValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_strings(&self) -> Option<&[String]> {
+        match self {
+            DynValue::Strings(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            DynValue::Boolean(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            DynValue::Numeric(n) => Some(n),
+            _ => None,
+        }
+    }
+}
+
+/// Describes a key whose type is only known at runtime
+///
+/// This is an alternative to implementing `Entry` for vendor keys such as
+/// `X-KDE-Protocols`:
+///
+/// ```
+/// use libdopen::{parse, EntryDescriptor, ValueType};
+///
+/// const PROTOCOLS: EntryDescriptor = EntryDescriptor::new("X-KDE-Protocols", ValueType::Strings);
+///
+/// let entry = parse("[Desktop Entry]\nX-KDE-Protocols=http;ftp;\n").unwrap();
+/// let value = entry.main_group().unwrap().get_dyn(&PROTOCOLS).unwrap();
+/// assert_eq!(value.as_strings(), Some(&["http".to_string(), "ftp".to_string()][..]));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryDescriptor {
+    pub name: Cow<'static, str>,
+    pub value_type: ValueType,
+}
+
+impl EntryDescriptor {
+    pub const fn new(name: &'static str, value_type: ValueType) -> EntryDescriptor {
+        EntryDescriptor {
+            name: Cow::Borrowed(name),
+            value_type,
+        }
+    }
+
+    /// Descriptor for a key name that isn't known at compile time
+    pub fn with_name(name: String, value_type: ValueType) -> EntryDescriptor {
+        EntryDescriptor {
+            name: Cow::Owned(name),
+            value_type,
+        }
+    }
+
+    /// Deserialize a raw value as this descriptor's type
+    ///
+    /// If `lenient` is set, booleans also accept `0` and `1`.
+    pub fn deserialize(&self, raw: &str, lenient: bool) -> Option<DynValue> {
+        match self.value_type {
+            ValueType::String | ValueType::LocaleString => {
+                Some(DynValue::String(util::unescape_value(raw)))
+            }
+            ValueType::Strings | ValueType::LocaleStrings => {
+                Some(DynValue::Strings(util::split_value_str(raw).collect()))
+            }
+            ValueType::Boolean => util::parse_bool(raw, lenient).map(DynValue::Boolean),
+            ValueType::Numeric => raw.parse().ok().map(DynValue::Numeric),
+        }
+    }
+}
+
+/// A set of `EntryDescriptor`s, looked up by key name
+#[derive(Debug, Clone, Default)]
+pub struct EntryRegistry(HashMap<String, EntryDescriptor>);
+
+impl EntryRegistry {
+    pub fn new() -> EntryRegistry {
+        EntryRegistry::default()
+    }
+
+    /// Add a descriptor, returning the one previously registered for its key
+    pub fn register(&mut self, descriptor: EntryDescriptor) -> Option<EntryDescriptor> {
+        self.0.insert(descriptor.name.to_string(), descriptor)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&EntryDescriptor> {
+        self.0.get(name)
+    }
+
+    /// Iterate over the registered descriptors in arbitrary order
+    pub fn descriptors(&self) -> impl Iterator<Item = &EntryDescriptor> {
+        self.0.values()
+    }
+
+    /// Get a registered key from a group
+    ///
+    /// Returns `None` if the key isn't registered, missing, or invalid.
+    pub fn get_from(&self, group: &Group, name: &str) -> Option<DynValue> {
+        group.get_dyn(self.get(name)?)
+    }
+}
+
+impl Group {
+    /// Get the value of a key described at runtime
+    ///
+    /// Localized types use the locale of the current process, see
+    /// `get_localized_auto`.
+    pub fn get_dyn(&self, descriptor: &EntryDescriptor) -> Option<DynValue> {
+        match current_locale() {
+            Some(locale) if descriptor.value_type.is_localized() => {
+                self.get_dyn_localized(descriptor, &locale)
+            }
+            _ => descriptor.deserialize(self.get_raw(&descriptor.name)?, self.is_lenient()),
+        }
+    }
+
+    /// Get the value of a key described at runtime for a locale
+    ///
+    /// Non-localized types ignore the locale.
+    pub fn get_dyn_localized(
+        &self,
+        descriptor: &EntryDescriptor,
+        locale: &str,
+    ) -> Option<DynValue> {
+        let raw = match descriptor.value_type.is_localized() {
+            true => self.get_raw_localized(&descriptor.name, locale)?,
+            false => self.get_raw(&descriptor.name)?,
+        };
+        descriptor.deserialize(raw, self.is_lenient())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, ParseOptions};
+
+    #[test]
+    fn get_dyn_test() {
+        let input = "\
+[Desktop Entry]
+X-Vendor-Title=Hello\\sWorld
+X-Vendor-Title[de]=Hallo
+X-Vendor-Tags=a;b\\;c;
+X-Vendor-Enabled=1
+X-GNOME-Autostart-Delay=3
+";
+        let entry = parse(input).unwrap();
+        let group = entry.main_group().unwrap();
+
+        let title = EntryDescriptor::new("X-Vendor-Title", ValueType::LocaleString);
+        assert_eq!(
+            group.get_dyn_localized(&title, "de_DE"),
+            Some(DynValue::String("Hallo".into()))
+        );
+        assert_eq!(
+            group.get_dyn_localized(&title, "fr"),
+            Some(DynValue::String("Hello World".into()))
+        );
+
+        let mut registry = EntryRegistry::new();
+        registry.register(EntryDescriptor::new("X-Vendor-Tags", ValueType::Strings));
+        registry.register(EntryDescriptor::new("X-Vendor-Enabled", ValueType::Boolean));
+        registry.register(EntryDescriptor::with_name(
+            "X-GNOME-Autostart-Delay".into(),
+            ValueType::Numeric,
+        ));
+        assert_eq!(
+            registry.get_from(group, "X-Vendor-Tags"),
+            Some(DynValue::Strings(vec!["a".into(), "b;c".into()]))
+        );
+        assert_eq!(
+            registry
+                .get_from(group, "X-GNOME-Autostart-Delay")
+                .and_then(|v| v.as_f64()),
+            Some(3.0)
+        );
+        assert_eq!(registry.get_from(group, "X-Vendor-Enabled"), None);
+        assert_eq!(registry.get_from(group, "X-Vendor-Title"), None);
+
+        let entry = ParseOptions::new().lenient(true).parse(input).unwrap();
+        assert_eq!(
+            registry.get_from(entry.main_group().unwrap(), "X-Vendor-Enabled"),
+            Some(DynValue::Boolean(true))
+        );
+    }
+}
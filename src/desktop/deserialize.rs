@@ -0,0 +1,128 @@
+use super::entries::Entry;
+use super::error::DeserializeError;
+use super::model::Group;
+
+/// A type that can be built from the entries of a `Group`
+///
+/// See `GroupDeserializer` for the usual way to implement this.
+pub trait FromGroup: Sized {
+    fn from_group(group: &Group) -> Result<Self, DeserializeError>;
+}
+
+/// Helper for reading `Entry` types out of a group into struct fields
+///
+/// ```
+/// use libdopen::{DeserializeError, FromGroup, Group, GroupDeserializer};
+/// use libdopen::entries::{Exec, Name, Terminal};
+///
+/// struct MyApp {
+///     name: Name,
+///     exec: Exec,
+///     terminal: Option<Terminal>,
+/// }
+///
+/// impl FromGroup for MyApp {
+///     fn from_group(group: &Group) -> Result<Self, DeserializeError> {
+///         let de = GroupDeserializer::new(group);
+///         Ok(MyApp {
+///             name: de.required()?,
+///             exec: de.required()?,
+///             terminal: de.optional()?,
+///         })
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct GroupDeserializer<'a> {
+    group: &'a Group,
+}
+
+impl<'a> GroupDeserializer<'a> {
+    pub fn new(group: &'a Group) -> GroupDeserializer<'a> {
+        GroupDeserializer { group }
+    }
+
+    /// Get an entry that must be present and valid
+    pub fn required<T: Entry>(&self) -> Result<T, DeserializeError> {
+        self.optional()?
+            .ok_or_else(|| DeserializeError::MissingKey {
+                group: self.group.name().into(),
+                key: T::name(),
+            })
+    }
+
+    /// Get an entry that may be missing, but must be valid if present
+    pub fn optional<T: Entry>(&self) -> Result<Option<T>, DeserializeError> {
+        self.group
+            .get_checked()
+            .map_err(|e| DeserializeError::InvalidValue {
+                group: self.group.name().into(),
+                key: e.key,
+                value: e.value,
+            })
+    }
+}
+
+impl Group {
+    /// Deserialize the group into a `FromGroup` type
+    pub fn deserialize_into<T: FromGroup>(&self) -> Result<T, DeserializeError> {
+        T::from_group(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::{Exec, Name, Terminal};
+    use crate::parse;
+
+    #[derive(Debug, PartialEq)]
+    struct App {
+        name: Name,
+        exec: Exec,
+        terminal: Option<Terminal>,
+    }
+
+    impl FromGroup for App {
+        fn from_group(group: &Group) -> Result<Self, DeserializeError> {
+            let de = GroupDeserializer::new(group);
+            Ok(App {
+                name: de.required()?,
+                exec: de.required()?,
+                terminal: de.optional()?,
+            })
+        }
+    }
+
+    #[test]
+    fn deserialize_into_test() {
+        let entry = parse("[Desktop Entry]\nName=Foo\nExec=foo %U\n").unwrap();
+        assert_eq!(
+            entry["Desktop Entry"].deserialize_into::<App>(),
+            Ok(App {
+                name: Name("Foo".into()),
+                exec: Exec("foo %U".into()),
+                terminal: None,
+            })
+        );
+
+        let entry = parse("[Desktop Entry]\nName=Foo\n").unwrap();
+        assert_eq!(
+            entry["Desktop Entry"].deserialize_into::<App>(),
+            Err(DeserializeError::MissingKey {
+                group: "Desktop Entry".into(),
+                key: "Exec",
+            })
+        );
+
+        let entry = parse("[Desktop Entry]\nName=Foo\nExec=foo\nTerminal=yes\n").unwrap();
+        assert_eq!(
+            entry["Desktop Entry"].deserialize_into::<App>(),
+            Err(DeserializeError::InvalidValue {
+                group: "Desktop Entry".into(),
+                key: "Terminal",
+                value: "yes".into(),
+            })
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use std::slice;
+
+use super::model::{DesktopEntry, Group, DESKTOP_ENTRY_NAME};
+
+/// A single difference between two versions of an entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyChange {
+    Added {
+        group: String,
+        key: String,
+        value: String,
+    },
+    Removed {
+        group: String,
+        key: String,
+        value: String,
+    },
+    Changed {
+        group: String,
+        key: String,
+        old: String,
+        new: String,
+    },
+}
+
+impl KeyChange {
+    /// The name of the group containing the key
+    pub fn group(&self) -> &str {
+        match self {
+            KeyChange::Added { group, .. }
+            | KeyChange::Removed { group, .. }
+            | KeyChange::Changed { group, .. } => group,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        match self {
+            KeyChange::Added { key, .. }
+            | KeyChange::Removed { key, .. }
+            | KeyChange::Changed { key, .. } => key,
+        }
+    }
+}
+
+/// The keys that differ between two versions of an entry, from `DesktopEntry::diff`
+///
+/// Raw values are compared, so a value that was only re-escaped counts as
+/// changed.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct EntryDiff(Vec<KeyChange>);
+
+impl EntryDiff {
+    /// Iterate over the changes, grouped by group in file order
+    pub fn changes(&self) -> slice::Iter<'_, KeyChange> {
+        self.0.iter()
+    }
+
+    /// Check if the entries were the same
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Check if a key in a group was added, removed or changed
+    pub fn contains(&self, group: &str, key: &str) -> bool {
+        self.0.iter().any(|c| c.group() == group && c.key() == key)
+    }
+
+    /// Check if a key in the "Desktop Entry" group was added, removed or changed
+    ///
+    /// Localized variants are included, so `Icon` also matches `Icon[de]`.
+    pub fn main_key_changed(&self, key: &str) -> bool {
+        self.0.iter().any(|c| {
+            c.group() == DESKTOP_ENTRY_NAME
+                && c.key()
+                    .strip_prefix(key)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('['))
+        })
+    }
+}
+
+impl DesktopEntry {
+    /// Compare with a newer version of the same entry
+    ///
+    /// Groups only present in one version count as all of their keys being
+    /// added or removed.
+    pub fn diff(&self, newer: &DesktopEntry) -> EntryDiff {
+        let mut changes = Vec::new();
+        for old in self.groups() {
+            match newer.group(old.name()) {
+                Some(new) => diff_group(old, new, &mut changes),
+                None => changes.extend(old.values().iter().map(|(k, v)| KeyChange::Removed {
+                    group: old.name().into(),
+                    key: k.clone(),
+                    value: v.clone(),
+                })),
+            }
+        }
+        for new in newer.groups().filter(|g| self.group(g.name()).is_none()) {
+            changes.extend(new.values().iter().map(|(k, v)| KeyChange::Added {
+                group: new.name().into(),
+                key: k.clone(),
+                value: v.clone(),
+            }));
+        }
+        EntryDiff(changes)
+    }
+}
+
+fn diff_group(old: &Group, new: &Group, changes: &mut Vec<KeyChange>) {
+    let group = old.name();
+    for (key, value) in old.values() {
+        match new.values().get(key) {
+            Some(v) if v == value => {}
+            Some(v) => changes.push(KeyChange::Changed {
+                group: group.into(),
+                key: key.clone(),
+                old: value.clone(),
+                new: v.clone(),
+            }),
+            None => changes.push(KeyChange::Removed {
+                group: group.into(),
+                key: key.clone(),
+                value: value.clone(),
+            }),
+        }
+    }
+    for (key, value) in new.values() {
+        if !old.values().contains_key(key) {
+            changes.push(KeyChange::Added {
+                group: group.into(),
+                key: key.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn diff_test() {
+        let old = parse(
+            "\
+[Desktop Entry]
+Name=Foo
+Icon=foo
+Exec=foo
+
+[Desktop Action old]
+Name=Old
+",
+        )
+        .unwrap();
+        let new = parse(
+            "\
+[Desktop Entry]
+Name=Foo
+Icon[de]=foo-de
+Exec=foo --new
+
+[Desktop Action new]
+Name=New
+",
+        )
+        .unwrap();
+        let diff = old.diff(&new);
+        let changes: Vec<_> = diff.changes().cloned().collect();
+        assert_eq!(
+            changes,
+            vec![
+                KeyChange::Removed {
+                    group: "Desktop Entry".into(),
+                    key: "Icon".into(),
+                    value: "foo".into(),
+                },
+                KeyChange::Changed {
+                    group: "Desktop Entry".into(),
+                    key: "Exec".into(),
+                    old: "foo".into(),
+                    new: "foo --new".into(),
+                },
+                KeyChange::Added {
+                    group: "Desktop Entry".into(),
+                    key: "Icon[de]".into(),
+                    value: "foo-de".into(),
+                },
+                KeyChange::Removed {
+                    group: "Desktop Action old".into(),
+                    key: "Name".into(),
+                    value: "Old".into(),
+                },
+                KeyChange::Added {
+                    group: "Desktop Action new".into(),
+                    key: "Name".into(),
+                    value: "New".into(),
+                },
+            ]
+        );
+        assert!(diff.main_key_changed("Icon"));
+        assert!(diff.main_key_changed("Exec"));
+        assert!(!diff.main_key_changed("Name"));
+        assert!(!diff.main_key_changed("Ico"));
+        assert!(diff.contains("Desktop Action new", "Name"));
+        assert!(old.diff(&old).is_empty());
+    }
+}
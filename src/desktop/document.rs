@@ -0,0 +1,255 @@
+use std::fmt;
+use std::io;
+use std::slice;
+
+use super::model::{DesktopEntry, Group};
+
+/// A single line of a desktop file that isn't a group header
+#[derive(Debug, PartialEq, Clone)]
+pub enum Line {
+    /// An empty line, possibly containing whitespace
+    Blank(String),
+    /// A comment, without the leading `#`
+    Comment(String),
+    /// A key-value pair
+    Entry(KeyValue),
+}
+
+/// A key-value pair, along with the exact text separating the key from the value
+#[derive(Debug, PartialEq, Clone)]
+pub struct KeyValue {
+    key: String,
+    separator: String,
+    value: String,
+}
+
+impl KeyValue {
+    pub fn new(key: String, value: String) -> KeyValue {
+        KeyValue::with_separator(key, "=".into(), value)
+    }
+
+    pub(crate) fn with_separator(key: String, separator: String, value: String) -> KeyValue {
+        KeyValue {
+            key,
+            separator,
+            value,
+        }
+    }
+
+    /// The key, in its original case
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// The raw (still escaped) value
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+/// A group in a `DesktopDocument`, along with the comments and blank lines inside it
+#[derive(Debug, PartialEq, Clone)]
+pub struct DocumentGroup {
+    name: String,
+    lines: Vec<Line>,
+}
+
+impl DocumentGroup {
+    pub fn new(name: String) -> DocumentGroup {
+        DocumentGroup {
+            name,
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// All lines of the group, not including the header
+    pub fn lines(&self) -> slice::Iter<'_, Line> {
+        self.lines.iter()
+    }
+
+    pub(crate) fn push_line(&mut self, line: Line) {
+        self.lines.push(line);
+    }
+
+    /// Iterate over the key-value pairs of the group in file order
+    pub fn entries(&self) -> impl Iterator<Item = &KeyValue> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry(kv) => Some(kv),
+            _ => None,
+        })
+    }
+
+    /// Get the raw value for a key
+    ///
+    /// Unlike `Group::get_raw`, the key must match exactly.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries().find(|kv| kv.key == key).map(KeyValue::value)
+    }
+
+    /// Set the raw value of a key
+    ///
+    /// If the key already exists its value is replaced in place, otherwise
+    /// it is added after the last existing entry of the group.
+    pub fn set(&mut self, key: &str, value: String) {
+        for line in self.lines.iter_mut() {
+            if let Line::Entry(ref mut kv) = *line {
+                if kv.key == key {
+                    kv.value = value;
+                    return;
+                }
+            }
+        }
+        let pos = self
+            .lines
+            .iter()
+            .rposition(|l| matches!(l, Line::Entry(_)))
+            .map_or(0, |p| p + 1);
+        self.lines
+            .insert(pos, Line::Entry(KeyValue::new(key.into(), value)));
+    }
+
+    /// Remove a key from the group, returning its raw value if it was present
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let pos = self
+            .lines
+            .iter()
+            .position(|l| matches!(l, Line::Entry(kv) if kv.key == key))?;
+        match self.lines.remove(pos) {
+            Line::Entry(kv) => Some(kv.value),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Convert to a `Group`, dropping comments and formatting
+    pub fn to_group(&self) -> Group {
+        let values = self.entries().map(|kv| (kv.key.clone(), kv.value.clone()));
+        Group::new(self.name.clone(), values)
+    }
+}
+
+/// A desktop file that preserves comments, blank lines and ordering
+///
+/// Unlike `DesktopEntry`, a `DesktopDocument` can be written back out
+/// exactly as it was read, so it is suitable for editing files in place.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DesktopDocument {
+    /// Comments and blank lines before the first group
+    preamble: Vec<Line>,
+    groups: Vec<DocumentGroup>,
+    final_newline: bool,
+}
+
+impl DesktopDocument {
+    pub fn new() -> DesktopDocument {
+        DesktopDocument {
+            preamble: Vec::new(),
+            groups: Vec::new(),
+            final_newline: true,
+        }
+    }
+
+    pub(crate) fn from_parts(
+        preamble: Vec<Line>,
+        groups: Vec<DocumentGroup>,
+        final_newline: bool,
+    ) -> DesktopDocument {
+        DesktopDocument {
+            preamble,
+            groups,
+            final_newline,
+        }
+    }
+
+    /// Lines before the first group
+    pub fn preamble(&self) -> slice::Iter<'_, Line> {
+        self.preamble.iter()
+    }
+
+    /// Get an iterator over all groups in file order
+    pub fn groups(&self) -> slice::Iter<'_, DocumentGroup> {
+        self.groups.iter()
+    }
+
+    /// Get a group in the document by name
+    pub fn group(&self, name: &str) -> Option<&DocumentGroup> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    /// Get a mutable reference to a group in the document by name
+    pub fn group_mut(&mut self, name: &str) -> Option<&mut DocumentGroup> {
+        self.groups.iter_mut().find(|g| g.name == name)
+    }
+
+    /// Get a group by name, adding an empty one at the end if it doesn't exist
+    pub fn group_or_insert(&mut self, name: &str) -> &mut DocumentGroup {
+        match self.groups.iter().position(|g| g.name == name) {
+            Some(pos) => &mut self.groups[pos],
+            None => {
+                self.groups.push(DocumentGroup::new(name.into()));
+                self.groups.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Remove a group from the document
+    pub fn remove_group(&mut self, name: &str) -> Option<DocumentGroup> {
+        let pos = self.groups.iter().position(|g| g.name == name)?;
+        Some(self.groups.remove(pos))
+    }
+
+    /// Convert to a `DesktopEntry`, dropping comments and formatting
+    pub fn to_entry(&self) -> DesktopEntry {
+        DesktopEntry::new(self.groups.iter().map(DocumentGroup::to_group).collect())
+    }
+
+    /// Write the document in desktop file format
+    pub fn write_to<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "{}", self)
+    }
+}
+
+impl Default for DesktopDocument {
+    fn default() -> DesktopDocument {
+        DesktopDocument::new()
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Line::Blank(ref s) => f.write_str(s),
+            Line::Comment(ref s) => write!(f, "#{}", s),
+            Line::Entry(ref kv) => write!(f, "{}{}{}", kv.key, kv.separator, kv.value),
+        }
+    }
+}
+
+impl fmt::Display for DesktopDocument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        let mut write_line = |f: &mut fmt::Formatter, line: &dyn fmt::Display| {
+            if !first {
+                f.write_str("\n")?;
+            }
+            first = false;
+            write!(f, "{}", line)
+        };
+        for line in &self.preamble {
+            write_line(f, line)?;
+        }
+        for group in &self.groups {
+            write_line(f, &format_args!("[{}]", group.name))?;
+            for line in &group.lines {
+                write_line(f, line)?;
+            }
+        }
+        if self.final_newline && !first {
+            f.write_str("\n")?;
+        }
+        Ok(())
+    }
+}
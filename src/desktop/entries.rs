@@ -1,7 +1,12 @@
+use std::fmt;
+use std::num::ParseFloatError;
 use std::ops::Deref;
 use std::str::{FromStr, ParseBoolError};
 use std::string;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
 /// Type representing a single entry in a group
 pub trait Entry: FromStr {
     /// The name of the entry
@@ -14,16 +19,43 @@ pub trait Entry: FromStr {
     fn deserialize(v: &str) -> Option<Self> {
         v.parse().ok()
     }
+
+    /// Deserialize an entry value, also accepting deprecated forms.
+    ///
+    /// For booleans this accepts `0` and `1` as well as `true` and `false`.
+    /// Other entries are the same as with `deserialize`.
+    #[inline]
+    fn deserialize_lenient(v: &str) -> Option<Self> {
+        Self::deserialize(v)
+    }
+}
+
+/// An entry that can be written back to a group, with `Group::set`
+///
+/// This is separate from `Entry`, so types that are only ever read don't
+/// have to implement it.
+pub trait SerializeEntry: Entry {
+    /// Serialize the entry to a raw (escaped) value.
+    ///
+    /// This is the inverse of `Entry::deserialize`.
+    fn serialize(&self) -> String;
 }
 
 macro_rules! entry_type {
     ($(#[$a:meta])* $name:ident (bool)) => {
         $(#[$a])*
             #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            #[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
             pub struct $name(pub bool);
         impl Entry for $name {
             #[inline(always)]
             fn name() -> &'static str { stringify!($name) }
+            fn deserialize_lenient(v: &str) -> Option<$name> {
+                util::parse_bool(v, true).map($name)
+            }
+        }
+        impl SerializeEntry for $name {
+            fn serialize(&self) -> String { self.0.to_string() }
         }
         impl FromStr for $name {
             type Err = ParseBoolError;
@@ -38,13 +70,45 @@ macro_rules! entry_type {
             }
         }
     };
+    ($(#[$a:meta])* $name:ident(f64) = $key:literal) => {
+        $(#[$a])*
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+        pub struct $name(pub f64);
+        impl Entry for $name {
+            #[inline(always)]
+            fn name() -> &'static str { $key }
+        }
+        impl SerializeEntry for $name {
+            fn serialize(&self) -> String { self.0.to_string() }
+        }
+        impl FromStr for $name {
+            type Err = ParseFloatError;
+            fn from_str(s: &str) -> Result<$name, ParseFloatError> {
+                s.parse().map($name)
+            }
+        }
+        impl Deref for $name {
+            type Target = f64;
+            fn deref(&self) -> &f64 {
+                &self.0
+            }
+        }
+    };
     ($(#[$a:meta])* $name:ident(String)) => {
+        entry_type!($(#[$a])* $name(String) = stringify!($name));
+    };
+    ($(#[$a:meta])* $name:ident(String) = $key:expr) => {
         $(#[$a])*
         #[derive(Debug, Clone, PartialEq, Eq)]
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
         pub struct $name(pub String);
         impl Entry for $name {
             #[inline(always)]
-            fn name() -> &'static str { stringify!($name) }
+            fn name() -> &'static str { $key }
+        }
+        impl SerializeEntry for $name {
+            fn serialize(&self) -> String { util::escape_value(&self.0) }
         }
         impl FromStr for $name {
             type Err = string::ParseError;
@@ -58,20 +122,24 @@ macro_rules! entry_type {
                 &self.0
             }
         }
-        impl ToString for $name {
-            fn to_string(&self) -> String {
-                self.0.clone()
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(&self.0)
             }
         }
     };
     ($(#[$a:meta])* $name:ident(Vec<String>)) => {
         $(#[$a])*
         #[derive(Debug, Clone, PartialEq, Eq)]
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
         pub struct $name(pub Vec<String>);
         impl Entry for $name {
             #[inline(always)]
             fn name() -> &'static str { stringify!($name) }
         }
+        impl SerializeEntry for $name {
+            fn serialize(&self) -> String { util::join_values(&self.0) }
+        }
         impl FromStr for $name {
             type Err = string::ParseError;
             fn from_str(s: &str) -> Result<$name, string::ParseError> {
@@ -88,6 +156,7 @@ macro_rules! entry_type {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Type {
     Application,
     Link,
@@ -100,6 +169,16 @@ impl Entry for Type {
         "Type"
     }
 }
+impl SerializeEntry for Type {
+    fn serialize(&self) -> String {
+        match *self {
+            Type::Application => "Application".into(),
+            Type::Link => "Link".into(),
+            Type::Directory => "Directory".into(),
+            Type::Unknown(ref other) => util::escape_value(other),
+        }
+    }
+}
 impl FromStr for Type {
     type Err = string::ParseError;
 
@@ -113,47 +192,228 @@ impl FromStr for Type {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Category {
-    AudioVideo,
-    Audio,
-    Video,
-    Development,
-    Education,
-    Game,
-    Graphics,
-    Network,
-    Office,
-    Science,
-    Settings,
-    System,
-    Utility,
-    Custom(String),
+macro_rules! category_str {
+    ($name:ident) => {
+        stringify!($name)
+    };
+    ($name:ident $s:literal) => {
+        $s
+    };
 }
-impl FromStr for Category {
-    type Err = string::ParseError;
-    fn from_str(s: &str) -> Result<Category, string::ParseError> {
-        use self::Category::*;
-        Ok(match s {
-            "AudioVideo" => AudioVideo,
-            "Audio" => Audio,
-            "Video" => Video,
-            "Development" => Development,
-            "Education" => Education,
-            "Game" => Game,
-            "Graphics" => Graphics,
-            "Network" => Network,
-            "Office" => Office,
-            "Science" => Science,
-            "Settings" => Settings,
-            "System" => System,
-            "Utility" => Utility,
-            other => Custom(other.into()),
-        })
+
+macro_rules! categories {
+    (
+        main { $($main:ident),* $(,)? }
+        additional { $($add:ident $(= $s:literal)? => [$($rel:ident),*]),* $(,)? }
+    ) => {
+        /// A category from the desktop menu specification
+        ///
+        /// This covers the main and the registered additional categories.
+        /// Anything else, including `X-` categories, is `Custom`.
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+        pub enum Category {
+            $($main,)*
+            $(
+                $(#[cfg_attr(feature = "serde", serde(rename = $s))])?
+                $add,
+            )*
+            Custom(String),
+        }
+        impl Category {
+            /// The name of the category as it appears in a desktop file
+            pub fn as_str(&self) -> &str {
+                match *self {
+                    $(Category::$main => stringify!($main),)*
+                    $(Category::$add => category_str!($add $($s)?),)*
+                    Category::Custom(ref other) => other,
+                }
+            }
+
+            /// Check if this is one of the main categories menus are built from
+            pub fn is_main(&self) -> bool {
+                matches!(*self, $(Category::$main)|*)
+            }
+
+            /// The main categories an additional category belongs under
+            ///
+            /// The spec requires at least one of these to be listed alongside
+            /// the additional category. This is empty for main categories,
+            /// custom categories and the ones not tied to a main category,
+            /// such as desktop environments.
+            pub fn related_main(&self) -> &'static [Category] {
+                match *self {
+                    $(Category::$add => &[$(Category::$rel),*],)*
+                    _ => &[],
+                }
+            }
+        }
+        impl FromStr for Category {
+            type Err = string::ParseError;
+            fn from_str(s: &str) -> Result<Category, string::ParseError> {
+                Ok(match s {
+                    $(stringify!($main) => Category::$main,)*
+                    $(category_str!($add $($s)?) => Category::$add,)*
+                    other => Category::Custom(other.into()),
+                })
+            }
+        }
+    };
+}
+
+categories! {
+    main {
+        AudioVideo,
+        Audio,
+        Video,
+        Development,
+        Education,
+        Game,
+        Graphics,
+        Network,
+        Office,
+        Science,
+        Settings,
+        System,
+        Utility,
+    }
+    additional {
+        Building => [Development],
+        Debugger => [Development],
+        IDE => [Development],
+        GUIDesigner => [Development],
+        Profiling => [Development],
+        RevisionControl => [Development],
+        Translation => [Development],
+        Calendar => [Office],
+        ContactManagement => [Office],
+        Database => [Office, Development, AudioVideo],
+        Dictionary => [Office],
+        Chart => [Office],
+        Email => [Office, Network],
+        Finance => [Office],
+        FlowChart => [Office],
+        PDA => [Office],
+        ProjectManagement => [Office, Development],
+        Presentation => [Office],
+        Spreadsheet => [Office],
+        WordProcessor => [Office],
+        Graphics2D = "2DGraphics" => [Graphics],
+        VectorGraphics => [Graphics],
+        RasterGraphics => [Graphics],
+        Graphics3D = "3DGraphics" => [Graphics],
+        Scanning => [Graphics],
+        OCR => [Graphics],
+        Photography => [Graphics, Office],
+        Publishing => [Graphics, Office],
+        Viewer => [Graphics, Office],
+        TextTools => [Utility],
+        DesktopSettings => [Settings],
+        HardwareSettings => [Settings],
+        Printing => [Settings],
+        PackageManager => [Settings],
+        Dialup => [Network],
+        InstantMessaging => [Network],
+        Chat => [Network],
+        IRCClient => [Network],
+        Feed => [Network],
+        FileTransfer => [Network],
+        HamRadio => [Network, Audio],
+        News => [Network],
+        P2P => [Network],
+        RemoteAccess => [Network],
+        Telephony => [Network],
+        TelephonyTools => [Utility],
+        VideoConference => [Network],
+        WebBrowser => [Network],
+        WebDevelopment => [Network, Development],
+        Midi => [AudioVideo, Audio],
+        Mixer => [AudioVideo, Audio],
+        Sequencer => [AudioVideo, Audio],
+        Tuner => [AudioVideo, Audio],
+        TV => [AudioVideo, Video],
+        AudioVideoEditing => [Audio, Video, AudioVideo],
+        Player => [Audio, Video, AudioVideo],
+        Recorder => [Audio, Video, AudioVideo],
+        DiscBurning => [AudioVideo],
+        ActionGame => [Game],
+        AdventureGame => [Game],
+        ArcadeGame => [Game],
+        BoardGame => [Game],
+        BlocksGame => [Game],
+        CardGame => [Game],
+        KidsGame => [Game],
+        LogicGame => [Game],
+        RolePlaying => [Game],
+        Shooter => [Game],
+        Simulation => [Game],
+        SportsGame => [Game],
+        StrategyGame => [Game],
+        Art => [Education, Science],
+        Construction => [Education, Science],
+        Music => [AudioVideo, Education],
+        Languages => [Education, Science],
+        ArtificialIntelligence => [Education, Science],
+        Astronomy => [Education, Science],
+        Biology => [Education, Science],
+        Chemistry => [Education, Science],
+        ComputerScience => [Education, Science],
+        DataVisualization => [Education, Science],
+        Economy => [Education, Science],
+        Electricity => [Education, Science],
+        Geography => [Education, Science],
+        Geology => [Education, Science],
+        Geoscience => [Education, Science],
+        History => [Education, Science],
+        Humanities => [Education, Science],
+        ImageProcessing => [Education, Science],
+        Literature => [Education, Science],
+        Maps => [Education, Science, Utility],
+        Math => [Education, Science],
+        NumericalAnalysis => [Education, Science],
+        MedicalSoftware => [Education, Science],
+        Physics => [Education, Science],
+        Robotics => [Education, Science],
+        Spirituality => [Education, Science, Utility],
+        Sports => [Education, Science],
+        ParallelComputing => [Education, Science],
+        Amusement => [],
+        Archiving => [Utility],
+        Compression => [Utility],
+        Electronics => [],
+        Emulator => [System, Game],
+        Engineering => [],
+        FileTools => [Utility, System],
+        FileManager => [System],
+        TerminalEmulator => [System],
+        Filesystem => [System],
+        Monitor => [System, Network],
+        Security => [Settings, System],
+        Accessibility => [Settings, Utility],
+        Calculator => [Utility],
+        Clock => [Utility],
+        TextEditor => [Utility],
+        Documentation => [],
+        Adult => [],
+        Core => [],
+        KDE => [],
+        GNOME => [],
+        XFCE => [],
+        DDE => [],
+        GTK => [],
+        Qt => [],
+        Motif => [],
+        Java => [],
+        ConsoleOnly => [],
+        Screensaver => [],
+        TrayIcon => [],
+        Applet => [],
+        Shell => [],
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
 pub struct Categories(Vec<Category>);
 impl Entry for Categories {
     #[inline(always)]
@@ -161,16 +421,33 @@ impl Entry for Categories {
         "Categories"
     }
 }
+impl SerializeEntry for Categories {
+    fn serialize(&self) -> String {
+        util::join_values(self.0.iter().map(Category::as_str))
+    }
+}
 impl FromStr for Categories {
     type Err = string::ParseError;
     fn from_str(s: &str) -> Result<Categories, string::ParseError> {
         Ok(Categories(
             util::split_value_str(s)
-                .map(|v| v.parse::<Category>().unwrap())
+                .map(|v| v.parse::<Category>().unwrap_or_else(|e| match e {}))
                 .collect(),
         ))
     }
 }
+impl Categories {
+    /// The main category to list the application under in a menu
+    ///
+    /// This is the first main category listed, or failing that the first
+    /// main category related to one of the additional categories.
+    pub fn menu_section(&self) -> Option<&Category> {
+        self.0
+            .iter()
+            .find(|c| c.is_main())
+            .or_else(|| self.0.iter().find_map(|c| c.related_main().first()))
+    }
+}
 impl Deref for Categories {
     type Target = [Category];
     fn deref(&self) -> &[Category] {
@@ -193,13 +470,106 @@ entry_type!(Exec(String));
 entry_type!(Path(String));
 entry_type!(Terminal(bool));
 entry_type!(Actions(Vec<String>));
-entry_type!(MimeType(Vec<String>));
 entry_type!(Implements(Vec<String>));
 entry_type!(Keywords(Vec<String>));
 entry_type!(StartupNotify(bool));
 entry_type!(StartupWMClass(String));
 entry_type!(URL(String));
+entry_type!(PrefersNonDefaultGPU(bool));
+entry_type!(SingleMainWindow(bool));
+entry_type!(
+    /// Seconds to wait before starting an autostart entry in GNOME
+    GnomeAutostartDelay(f64) = "X-GNOME-Autostart-Delay"
+);
+entry_type!(
+    /// The application ID of an entry exported by Flatpak
+    Flatpak(String) = "X-Flatpak"
+);
+
+/// A mime type from the `MimeType` key, which may be a wildcard like `image/*`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct MimePattern(String);
+impl MimePattern {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The part before the `/`, such as `image`
+    pub fn media_type(&self) -> &str {
+        self.0.split_once('/').map_or(&self.0, |(media, _)| media)
+    }
+
+    /// The part after the `/`, such as `png` or `*`
+    pub fn subtype(&self) -> Option<&str> {
+        self.0.split_once('/').map(|(_, subtype)| subtype)
+    }
 
+    pub fn is_wildcard(&self) -> bool {
+        self.subtype() == Some("*")
+    }
+
+    /// Check if a mime type such as `image/png` matches this pattern
+    ///
+    /// Mime types are compared ignoring ASCII case.
+    pub fn matches(&self, mime_type: &str) -> bool {
+        match mime_type.split_once('/') {
+            Some((media, _)) if self.is_wildcard() => media.eq_ignore_ascii_case(self.media_type()),
+            _ => mime_type.eq_ignore_ascii_case(&self.0),
+        }
+    }
+}
+impl FromStr for MimePattern {
+    type Err = string::ParseError;
+    fn from_str(s: &str) -> Result<MimePattern, string::ParseError> {
+        Ok(MimePattern(s.into()))
+    }
+}
+impl fmt::Display for MimePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct MimeType(pub Vec<MimePattern>);
+impl MimeType {
+    /// Check if any of the patterns match a mime type
+    pub fn matches(&self, mime_type: &str) -> bool {
+        self.0.iter().any(|p| p.matches(mime_type))
+    }
+}
+impl Entry for MimeType {
+    #[inline(always)]
+    fn name() -> &'static str {
+        "MimeType"
+    }
+}
+impl SerializeEntry for MimeType {
+    fn serialize(&self) -> String {
+        util::join_values(self.0.iter().map(MimePattern::as_str))
+    }
+}
+impl FromStr for MimeType {
+    type Err = string::ParseError;
+    fn from_str(s: &str) -> Result<MimeType, string::ParseError> {
+        Ok(MimeType(
+            util::split_value_str(s)
+                .filter(|v| !v.is_empty())
+                .map(MimePattern)
+                .collect(),
+        ))
+    }
+}
+impl Deref for MimeType {
+    type Target = [MimePattern];
+    fn deref(&self) -> &[MimePattern] {
+        &self.0
+    }
+}
+
+#[cfg_attr(not(feature = "unstable"), doc(hidden))]
 pub mod util {
     use std::str::Chars;
 
@@ -208,7 +578,7 @@ pub mod util {
     /// This split a string value into a `Vec` for multiple values
     /// as described in
     /// https://standards.freedesktop.org/desktop-entry-spec/latest/ar01s03.html
-    pub fn split_value_str(s: &str) -> Values {
+    pub fn split_value_str(s: &str) -> Values<'_> {
         Values { inner: s.chars() }
     }
 
@@ -240,6 +610,60 @@ pub mod util {
         content
     }
 
+    /// Escape a string value
+    ///
+    /// This is the inverse of `unescape_value`, and should be used when
+    /// serializing any entries with string values.
+    pub fn escape_value(s: &str) -> String {
+        let mut content = String::with_capacity(s.len());
+        for ch in s.chars() {
+            match ch {
+                '\\' => content.push_str("\\\\"),
+                '\n' => content.push_str("\\n"),
+                '\t' => content.push_str("\\t"),
+                '\r' => content.push_str("\\r"),
+                _ => content.push(ch),
+            }
+        }
+        // Leading spaces would be stripped when parsing
+        if content.starts_with(' ') {
+            content.replace_range(..1, "\\s");
+        }
+        content
+    }
+
+    /// Parse a boolean value
+    ///
+    /// The spec only allows `true` and `false`, but older versions also
+    /// allowed `0` and `1`, which are accepted if `lenient` is set.
+    pub fn parse_bool(s: &str, lenient: bool) -> Option<bool> {
+        match s {
+            "true" => Some(true),
+            "false" => Some(false),
+            "1" if lenient => Some(true),
+            "0" if lenient => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Join multiple values with semicolons
+    ///
+    /// Each value is escaped, including any semicolons it contains, and
+    /// the result is terminated with a semicolon as recommended by the spec.
+    /// This is the inverse of `split_value_str`.
+    pub fn join_values<I, S>(values: I) -> String
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut content = String::new();
+        for value in values {
+            content.push_str(&escape_value(value.as_ref()).replace(';', "\\;"));
+            content.push(';');
+        }
+        content
+    }
+
     /// Iterator over multiple string values in an entry.
     ///
     /// See `split_value_str`
@@ -280,6 +704,7 @@ pub mod util {
 #[cfg(test)]
 mod tests {
     use super::util::*;
+    use super::{Categories, Category, MimeType, SerializeEntry};
 
     macro_rules! assert_strings_eq {
         ($expected:expr, [$($s:expr),*]) => {
@@ -305,6 +730,69 @@ mod tests {
         assert_strings_eq!(split_value_str("a\\\\;b\\"), ["a\\", "b\\"]);
     }
 
+    #[test]
+    fn escape_value_test() {
+        assert_eq!(escape_value(" a\tb\nc\rd\\e "), "\\sa\\tb\\nc\\rd\\\\e ");
+        let original = " \n\t\r\\a;";
+        assert_eq!(unescape_value(&escape_value(original)), original);
+    }
+
+    #[test]
+    fn join_values_test() {
+        assert_eq!(join_values(Vec::<String>::new()), "");
+        assert_eq!(join_values(["a", "b;c", "d\\"]), "a;b\\;c;d\\\\;");
+        assert_strings_eq!(
+            split_value_str(&join_values(["a", "b;c", "d\\"])),
+            ["a", "b;c", "d\\"]
+        );
+    }
+
+    #[test]
+    fn parse_bool_test() {
+        assert_eq!(parse_bool("true", false), Some(true));
+        assert_eq!(parse_bool("false", false), Some(false));
+        assert_eq!(parse_bool("1", false), None);
+        assert_eq!(parse_bool("1", true), Some(true));
+        assert_eq!(parse_bool("0", true), Some(false));
+        assert_eq!(parse_bool("yes", true), None);
+    }
+
+    #[test]
+    fn category_test() {
+        let categories: Categories = "2DGraphics;Office;X-Custom;".parse().unwrap();
+        assert_eq!(
+            &*categories,
+            [
+                Category::Graphics2D,
+                Category::Office,
+                Category::Custom("X-Custom".into())
+            ]
+        );
+        assert_eq!(categories[0].as_str(), "2DGraphics");
+        assert!(!categories[0].is_main());
+        assert_eq!(categories[0].related_main(), [Category::Graphics]);
+        assert!(categories[1].is_main());
+        assert_eq!(categories.menu_section(), Some(&Category::Office));
+
+        let categories: Categories = "GNOME;GTK;Player;".parse().unwrap();
+        assert_eq!(categories.menu_section(), Some(&Category::Audio));
+        let categories: Categories = "Qt;".parse().unwrap();
+        assert_eq!(categories.menu_section(), None);
+    }
+
+    #[test]
+    fn mime_type_test() {
+        let mime: MimeType = "image/*;text/plain;;".parse().unwrap();
+        assert_eq!(mime.len(), 2);
+        assert!(mime[0].is_wildcard());
+        assert_eq!(mime[0].media_type(), "image");
+        assert!(mime.matches("image/png"));
+        assert!(mime.matches("Text/Plain"));
+        assert!(!mime.matches("text/html"));
+        assert!(!mime.matches("image"));
+        assert_eq!(mime.serialize(), "image/*;text/plain;");
+    }
+
     #[test]
     fn unescape_value_test() {
         assert_eq!(
@@ -313,4 +801,19 @@ mod tests {
         );
         assert_eq!(unescape_value("a\\"), "a\\".to_string());
     }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_test() {
+        use super::{Name, Type};
+
+        assert_eq!(
+            serde_json::to_string(&Name("Foo".into())).unwrap(),
+            r#""Foo""#
+        );
+        assert_eq!(
+            serde_json::to_string(&Type::Application).unwrap(),
+            r#""Application""#
+        );
+    }
 }
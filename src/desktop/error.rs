@@ -1,55 +1,299 @@
-use std::error;
 use std::fmt;
 use std::io;
 use std::str::Utf8Error;
 
 use nom::error::ErrorKind;
+use thiserror::Error;
 
-#[derive(Debug)]
+use super::execute;
+use super::validate::{Diagnostic, Severity};
+
+/// Any error produced by this crate
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// Failed to parse a desktop file
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    /// A key had an invalid value
+    #[error(transparent)]
+    Entry(#[from] EntryError),
+    /// An entry was missing required keys, or had keys not allowed for its type
+    #[error(transparent)]
+    RequiredKeys(#[from] RequiredKeyError),
+    /// A group couldn't be deserialized into a `FromGroup` type
+    #[error(transparent)]
+    Deserialize(#[from] DeserializeError),
+    /// An entry doesn't follow the spec, from `DesktopEntry::check_valid`
+    #[error(transparent)]
+    Validate(#[from] ValidationError),
+    /// Failed to build or launch a command
+    #[error(transparent)]
+    Launch(#[from] execute::Error),
+    /// IO error outside of parsing
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum ParseError {
     /// Syntax error
-    Syntax(String, ErrorKind),
+    #[error("Invalid syntax [{1:?}] at \"{0}\" {2}")]
+    Syntax(String, ErrorKind, Location),
     /// Invalid UTF-8 sequence
-    NonUtf8,
+    #[error("Invalid Ut8 byte sequence in input {0}")]
+    NonUtf8(Location),
+    /// A key appeared more than once in a group
+    #[error("Duplicate key {0}")]
+    DuplicateKey(Location),
+    /// A group appeared more than once
+    #[error("Duplicate group {0}")]
+    DuplicateGroup(Location),
+    /// The input exceeded a limit set in `ParseOptions`
+    #[error("Input has {0} {1}")]
+    LimitExceeded(Limit, Location),
+    /// The input uses `Encoding=Legacy-Mixed`, so it can't be borrowed
+    #[error("Legacy-Mixed encoded input has to be transcoded")]
+    LegacyEncoding,
     /// IO error
-    IO(io::Error),
+    #[error(transparent)]
+    IO(#[from] io::Error),
 }
 
-// Mostly needed for tests
-impl std::cmp::PartialEq for ParseError {
-    fn eq(&self, other: &Self) -> bool {
-        use self::ParseError::*;
-        match (self, other) {
-            (Syntax(ref s1, k1), Syntax(ref s2, k2)) => k1 == k2 && s1 == s2,
-            (NonUtf8, NonUtf8) => true,
-            _ => false,
+/// A key had a value that couldn't be deserialized, from `Group::get_checked`
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Invalid value \"{value}\" for key {key}")]
+pub struct EntryError {
+    pub key: &'static str,
+    pub value: String,
+}
+
+/// Error from `Group::deserialize_into`
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum DeserializeError {
+    /// A required key was missing
+    #[error("Missing key {key} in group [{group}]")]
+    MissingKey { group: String, key: &'static str },
+    /// A key had a value that couldn't be deserialized
+    #[error("Invalid value \"{value}\" for key {key} in group [{group}]")]
+    InvalidValue {
+        group: String,
+        key: &'static str,
+        value: String,
+    },
+}
+
+/// Error from `DesktopEntry::check_required_keys`
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[non_exhaustive]
+pub enum RequiredKeyError {
+    /// A key required for the type of entry was not set
+    #[error("Missing required key {0}")]
+    MissingKey(&'static str),
+    /// A required key had an invalid value, such as an unknown `Type`
+    #[error(transparent)]
+    InvalidValue(#[from] EntryError),
+    /// A key was set that isn't allowed for the type of entry
+    #[error("Key {key} is not allowed for entries of type {ty}")]
+    UnexpectedKey { key: &'static str, ty: String },
+}
+
+/// Error from `DesktopEntryBuilder::build`, which checks the required keys
+pub type BuildError = RequiredKeyError;
+
+/// Error from `DesktopEntry::check_valid`, with every problem found
+///
+/// At least one of the diagnostics has `Severity::Error`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ValidationError {
+    /// Just the diagnostics that violate the spec
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("Invalid desktop entry")?;
+        for diagnostic in self.errors() {
+            write!(fmt, "\n  {}", diagnostic)?;
         }
+        Ok(())
     }
 }
 
-impl fmt::Display for ParseError {
+impl std::error::Error for ValidationError {}
+
+/// A limit that can be set in `ParseOptions`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// Maximum size of the input in bytes
+    Size(usize),
+    /// Maximum number of groups
+    Groups(usize),
+    /// Maximum number of keys in a group
+    Keys(usize),
+}
+
+impl fmt::Display for Limit {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        use self::ParseError::*;
         match *self {
-            Syntax(ref i, kind) => write!(fmt, "Invalid syntax [{:?}] at \"{}\"", kind, i),
-            NonUtf8 => write!(fmt, "Invalid Ut8 byte sequence in input"),
-            IO(ref err) => write!(fmt, "{}", err),
+            Limit::Size(max) => write!(fmt, "more than {} bytes", max),
+            Limit::Groups(max) => write!(fmt, "more than {} groups", max),
+            Limit::Keys(max) => write!(fmt, "more than {} keys in a group", max),
+        }
+    }
+}
+
+/// A problem found by `parse_lenient` that didn't stop the parse
+#[derive(Debug, PartialEq, Error)]
+#[non_exhaustive]
+pub enum Warning {
+    /// A line that couldn't be parsed and was skipped
+    #[error("Skipped invalid line: {0}")]
+    InvalidLine(ParseError),
+    /// A key that appeared more than once in a group. The last value is kept.
+    #[error("Duplicate key {key} in group [{group}] (line {line})")]
+    DuplicateKey {
+        group: String,
+        key: String,
+        line: usize,
+    },
+    /// A group that appeared more than once. The groups are merged.
+    #[error("Duplicate group [{group}] (line {line})")]
+    DuplicateGroup { group: String, line: usize },
+}
+
+/// Where in the input a `ParseError` occurred
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    /// The group that was being parsed, if any
+    pub group: Option<String>,
+    /// The key that was being parsed, if any
+    pub key: Option<String>,
+    /// The line number, starting at 1
+    pub line: usize,
+    /// Number of bytes of input remaining when the error occurred,
+    /// used to compute `line` once the whole input is known
+    remaining: usize,
+}
+
+impl Location {
+    fn at(input: &[u8]) -> Location {
+        Location {
+            remaining: input.len(),
+            ..Location::default()
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.group, &self.key) {
+            (Some(g), Some(k)) => write!(fmt, "in group [{}], key {} (line {})", g, k, self.line),
+            (Some(g), None) => write!(fmt, "in group [{}] (line {})", g, self.line),
+            _ => write!(fmt, "at line {}", self.line),
         }
     }
 }
 
-impl error::Error for ParseError {
-    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+impl ParseError {
+    /// Get the location of the error, if it is a syntax or encoding error
+    pub fn location(&self) -> Option<&Location> {
         match *self {
-            ParseError::IO(ref err) => Some(err),
-            _ => None,
+            ParseError::Syntax(_, _, ref loc)
+            | ParseError::NonUtf8(ref loc)
+            | ParseError::DuplicateKey(ref loc)
+            | ParseError::DuplicateGroup(ref loc)
+            | ParseError::LimitExceeded(_, ref loc) => Some(loc),
+            ParseError::LegacyEncoding | ParseError::IO(_) => None,
         }
     }
+
+    fn location_mut(&mut self) -> Option<&mut Location> {
+        match *self {
+            ParseError::Syntax(_, _, ref mut loc)
+            | ParseError::NonUtf8(ref mut loc)
+            | ParseError::DuplicateKey(ref mut loc)
+            | ParseError::DuplicateGroup(ref mut loc)
+            | ParseError::LimitExceeded(_, ref mut loc) => Some(loc),
+            ParseError::LegacyEncoding | ParseError::IO(_) => None,
+        }
+    }
+
+    pub(crate) fn non_utf8(input: &[u8]) -> Self {
+        Self::NonUtf8(Location::at(input))
+    }
+
+    pub(crate) fn duplicate_key(input: &[u8]) -> Self {
+        Self::DuplicateKey(Location::at(input))
+    }
+
+    pub(crate) fn duplicate_group(input: &[u8]) -> Self {
+        Self::DuplicateGroup(Location::at(input))
+    }
+
+    pub(crate) fn limit(limit: Limit, input: &[u8]) -> Self {
+        Self::LimitExceeded(limit, Location::at(input))
+    }
+
+    /// Attribute the error to a group, unless it already has one
+    pub(crate) fn in_group(mut self, group: &str) -> Self {
+        if let Some(loc) = self.location_mut() {
+            loc.group.get_or_insert_with(|| group.to_owned());
+        }
+        self
+    }
+
+    /// Attribute the error to a key, unless it already has one
+    pub(crate) fn in_key(mut self, key: &str) -> Self {
+        if let Some(loc) = self.location_mut() {
+            loc.key.get_or_insert_with(|| key.to_owned());
+        }
+        self
+    }
+
+    /// Set the line number of the error directly
+    pub(crate) fn on_line(mut self, line: usize) -> Self {
+        if let Some(loc) = self.location_mut() {
+            loc.line = line;
+        }
+        self
+    }
+
+    /// Compute the line number of the error relative to the full input
+    pub(crate) fn locate(mut self, input: &[u8]) -> Self {
+        if let Some(loc) = self.location_mut() {
+            let offset = input.len().saturating_sub(loc.remaining);
+            loc.line = 1 + input[..offset].iter().filter(|&&c| c == b'\n').count();
+        }
+        self
+    }
 }
 
-impl From<io::Error> for ParseError {
-    fn from(err: io::Error) -> Self {
-        ParseError::IO(err)
+// Mostly needed for tests
+impl std::cmp::PartialEq for ParseError {
+    fn eq(&self, other: &Self) -> bool {
+        use self::ParseError::*;
+        match (self, other) {
+            (Syntax(ref s1, k1, ref l1), Syntax(ref s2, k2, ref l2)) => {
+                k1 == k2 && s1 == s2 && l1 == l2
+            }
+            (NonUtf8(ref l1), NonUtf8(ref l2))
+            | (DuplicateKey(ref l1), DuplicateKey(ref l2))
+            | (DuplicateGroup(ref l1), DuplicateGroup(ref l2)) => l1 == l2,
+            (LimitExceeded(m1, ref l1), LimitExceeded(m2, ref l2)) => m1 == m2 && l1 == l2,
+            (LegacyEncoding, LegacyEncoding) => true,
+            _ => false,
+        }
     }
 }
 
@@ -58,7 +302,7 @@ impl nom::error::ParseError<&[u8]> for ParseError {
         let input_sample = input[..std::cmp::min(input.len(), 16)]
             .escape_ascii()
             .to_string();
-        ParseError::Syntax(input_sample, kind)
+        ParseError::Syntax(input_sample, kind, Location::at(input))
     }
 
     fn append(_i: &[u8], _kind: ErrorKind, other: Self) -> Self {
@@ -67,7 +311,7 @@ impl nom::error::ParseError<&[u8]> for ParseError {
 }
 
 impl nom::error::FromExternalError<&[u8], Utf8Error> for ParseError {
-    fn from_external_error(_: &[u8], _: ErrorKind, _: Utf8Error) -> Self {
-        Self::NonUtf8
+    fn from_external_error(input: &[u8], _: ErrorKind, _: Utf8Error) -> Self {
+        Self::NonUtf8(Location::at(input))
     }
 }
@@ -1,83 +1,322 @@
+use std::borrow::Cow;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::io;
+#[cfg(unix)]
 use std::os::unix::process::CommandExt;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::slice;
 use std::str;
 
 use once_cell::sync::OnceCell;
 use regex::{self, Captures, Regex};
+use thiserror::Error;
 
-use super::entries::{Icon, Name};
-use super::model::DesktopEntry;
+use super::entries::{self, Icon, Name, Terminal, TryExec};
+use super::model::{current_locale, DesktopEntry};
+use super::uri;
 use crate::entries::Exec;
 
+#[cfg(feature = "dbus")]
+mod dbus;
+mod launcher;
+
+#[cfg(feature = "dbus")]
+pub use self::dbus::{DBusExecutor, DBusLauncher};
+pub use self::launcher::*;
+
 pub trait Executor {
     fn execute(self) -> Result<(), Error>;
 }
 
 #[derive(Clone)]
 pub struct ExecContext<'a> {
-    /// The Desktop Entry that is being executed
-    source: &'a DesktopEntry,
-    /// The path (or uri) to the desktop file
-    source_path: Option<String>,
+    /// The value for `%i`
+    icon: Option<String>,
+    /// The value for `%c`
+    name: Option<String>,
+    /// Where the desktop file came from, for `%k`
+    source: EntrySource,
     /// A list of files (or uris) to pass to the command
     args: &'a [String],
+    /// Whether the command has to run in a terminal emulator
+    terminal: bool,
+    /// The directory to run the command in, from the `Path` key
+    working_dir: Option<PathBuf>,
+    /// What to do if `working_dir` doesn't exist
+    missing_dir: MissingDir,
+    /// Refuse relative working directories
+    strict: bool,
+}
+
+impl<'a> ExecContext<'a> {
+    /// Context for running a command from `source`, passing it `args`
+    ///
+    /// `source` is where the desktop file was read from, used for `%k`. The
+    /// name and icon are localized for the locale of the current process,
+    /// see `current_locale`.
+    pub fn new(entry: &DesktopEntry, args: &'a [String], source: EntrySource) -> ExecContext<'a> {
+        ExecContext::with_locale(entry, args, source, current_locale().as_deref())
+    }
+
+    /// Like `new`, but localize the name and icon for `locale`
+    ///
+    /// With `None`, the unlocalized values are used.
+    pub fn with_locale(
+        entry: &DesktopEntry,
+        args: &'a [String],
+        source: EntrySource,
+        locale: Option<&str>,
+    ) -> ExecContext<'a> {
+        let group = entry.main_group();
+        let icon = group.and_then(|g| g.get_for_locale(locale));
+        let name = group.and_then(|g| g.get_for_locale(locale));
+        ExecContext {
+            icon: icon.map(|Icon(i)| i),
+            name: name.map(|Name(n)| n),
+            source,
+            args,
+            terminal: entry.get::<Terminal>().is_some_and(|Terminal(t)| t),
+            working_dir: entry
+                .get::<entries::Path>()
+                .filter(|entries::Path(p)| !p.is_empty())
+                .map(|entries::Path(p)| p.into()),
+            missing_dir: MissingDir::default(),
+            strict: false,
+        }
+    }
+
+    /// Context that isn't backed by a desktop entry
+    ///
+    /// This allows expanding field codes in ad-hoc command templates. `%i`,
+    /// `%c` and `%k` expand to nothing unless set with `icon`, `name` and
+    /// `source`.
+    pub fn with_args(args: &'a [String]) -> ExecContext<'a> {
+        ExecContext {
+            icon: None,
+            name: None,
+            source: EntrySource::None,
+            args,
+            terminal: false,
+            working_dir: None,
+            missing_dir: MissingDir::default(),
+            strict: false,
+        }
+    }
+
+    /// Set the icon used for `%i`
+    pub fn icon(mut self, icon: Option<String>) -> ExecContext<'a> {
+        self.icon = icon;
+        self
+    }
+
+    /// Set the name used for `%c`
+    pub fn name(mut self, name: Option<String>) -> ExecContext<'a> {
+        self.name = name;
+        self
+    }
+
+    /// Set the source used for `%k`
+    pub fn source(mut self, source: EntrySource) -> ExecContext<'a> {
+        self.source = source;
+        self
+    }
+
+    /// The desktop file ID of the source, see `EntrySource::desktop_id`
+    pub(crate) fn desktop_id(&self) -> Option<String> {
+        self.source.desktop_id()
+    }
+
+    /// Set whether the command runs in a terminal emulator, like `Terminal=true`
+    pub fn terminal(mut self, terminal: bool) -> ExecContext<'a> {
+        self.terminal = terminal;
+        self
+    }
+
+    /// Set the directory to run the command in, like the `Path` key
+    pub fn working_dir(mut self, dir: Option<PathBuf>) -> ExecContext<'a> {
+        self.working_dir = dir;
+        self
+    }
+
+    /// Set what happens if the working directory doesn't exist
+    pub fn missing_dir(mut self, missing_dir: MissingDir) -> ExecContext<'a> {
+        self.missing_dir = missing_dir;
+        self
+    }
+
+    /// Set whether to refuse relative working directories
+    ///
+    /// The spec doesn't say what a relative `Path` is relative to, so by
+    /// default it is resolved against the current directory of this process.
+    pub fn strict(mut self, strict: bool) -> ExecContext<'a> {
+        self.strict = strict;
+        self
+    }
+
+    /// The directory to run the command in, or `None` to keep the current one
+    fn resolve_working_dir(&self) -> Result<Option<&Path>, Error> {
+        let Some(dir) = self.working_dir.as_deref() else {
+            return Ok(None);
+        };
+        if self.strict && dir.is_relative() {
+            return Err(Error::RelativeWorkingDir(dir.into()));
+        }
+        if dir.is_dir() {
+            return Ok(Some(dir));
+        }
+        match self.missing_dir {
+            MissingDir::Error => Err(Error::MissingWorkingDir(dir.into())),
+            MissingDir::Inherit => Ok(None),
+        }
+    }
+}
+
+/// What to do when the working directory of an entry doesn't exist
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingDir {
+    /// Fail with `Error::MissingWorkingDir`
+    #[default]
+    Error,
+    /// Run the command in the current directory instead
+    Inherit,
 }
 
+/// Where a desktop entry was read from
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum EntrySource {
+    /// A local file, `%k` expands to the path
+    Path(PathBuf),
+    /// A non-local location such as a network share, `%k` expands to the URI
+    Uri(String),
+    /// The location isn't known, for example when parsing stdin, so `%k`
+    /// expands to nothing
+    #[default]
+    None,
+}
+
+impl EntrySource {
+    /// The value of `%k`
+    pub fn expand(&self) -> Cow<'_, str> {
+        match self {
+            EntrySource::Path(path) => path.to_string_lossy(),
+            EntrySource::Uri(uri) => Cow::Borrowed(uri),
+            EntrySource::None => Cow::Borrowed(""),
+        }
+    }
+
+    /// The desktop file ID of a local entry
+    ///
+    /// For a file below an `applications` directory, this is its path
+    /// relative to that directory with `/` replaced by `-`, such as
+    /// `kde-konsole.desktop` for `applications/kde/konsole.desktop`.
+    /// Otherwise it is the file name.
+    pub fn desktop_id(&self) -> Option<String> {
+        let EntrySource::Path(path) = self else {
+            return None;
+        };
+        let relative = path
+            .ancestors()
+            .skip(1)
+            .find(|dir| dir.file_name().is_some_and(|n| n == "applications"))
+            .and_then(|dir| path.strip_prefix(dir).ok())
+            .unwrap_or_else(|| Path::new(path.file_name().unwrap_or_default()));
+        let parts: Vec<_> = relative.iter().map(|p| p.to_string_lossy()).collect();
+        Some(parts.join("-")).filter(|id| !id.is_empty())
+    }
+}
+
+impl From<Option<String>> for EntrySource {
+    /// A local path if there is one
+    fn from(path: Option<String>) -> EntrySource {
+        path.map_or(EntrySource::None, |p| EntrySource::Path(p.into()))
+    }
+}
+
+#[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum Error {
+    #[error("Entry has no command to execute")]
     NoCommand,
+    #[error("Incomplete escape sequence in command")]
     IncompleteEscape,
+    #[error("Unterminated quote in command")]
     IncompleteQuote,
+    #[error("Reserved character {0:?} must be quoted")]
+    ReservedChar(char),
+    #[error("Character {0:?} must be escaped inside quotes")]
+    UnescapedChar(char),
+    #[error("Invalid escape sequence \\{0} in quoted argument")]
+    InvalidEscape(char),
+    #[error("Quotes must enclose a whole argument")]
+    PartialQuote,
+    #[error("Command has more than one file or url argument")]
     MultipleFileArgs,
-    ExecuteFailed,
+    #[error("TryExec program {0} is not installed")]
+    TryExecMissing(String),
+    #[error("No launcher supports this entry")]
+    NoLauncher,
+    #[error("No terminal emulator found to run the command in")]
+    NoTerminal,
+    #[error("Working directory {} does not exist", .0.display())]
+    MissingWorkingDir(PathBuf),
+    #[error("Working directory {} is not an absolute path", .0.display())]
+    RelativeWorkingDir(PathBuf),
+    #[error("Failed to execute command")]
+    ExecuteFailed(#[source] io::Error),
+    #[cfg(feature = "dbus")]
+    #[error("Failed to activate application over D-Bus")]
+    DBusFailed(#[source] zbus::Error),
 }
 
-fn split_command<'a>(command: &'a str) -> CommandWords<'a> {
-    CommandWords {
-        inner: command.chars(),
-    }
-}
+/// Characters that must be inside a quoted argument
+const RESERVED_CHARS: &str = " \t\n\"'\\><~|&;$*?#()`";
 
-struct CommandWords<'a> {
-    inner: str::Chars<'a>,
-}
+/// Split an `Exec` value into arguments following the quoting rules of the spec
+///
+/// `command` should already have the general string escapes removed, as
+/// `Exec` does. Arguments are separated by spaces, and an argument
+/// containing a reserved character must be enclosed in double quotes.
+/// Inside quotes, `"`, `` ` ``, `$` and `\` must be escaped with a backslash.
+/// Field codes are left as they are.
+pub fn tokenize(command: &str) -> Result<Vec<String>, Error> {
+    use self::Error::*;
 
-impl<'a> Iterator for CommandWords<'a> {
-    type Item = Result<String, Error>;
-    fn next(&mut self) -> Option<Self::Item> {
-        use self::Error::*;
-        if self.inner.as_str().is_empty() {
-            return None;
-        }
-        let mut result = String::with_capacity(self.inner.as_str().len());
-        let mut escaping = false;
-        let mut in_quotes = false;
-        while let Some(c) = self.inner.next() {
-            match c {
-                '"' if !escaping => in_quotes = !in_quotes,
-                '\\' if in_quotes => {
-                    if escaping {
-                        result.push('\\');
-                    }
-                    escaping = !escaping;
+    let mut args = Vec::new();
+    let mut chars = command.chars().peekable();
+    loop {
+        while chars.next_if_eq(&' ').is_some() {}
+        let mut arg = String::new();
+        match chars.next() {
+            None => return Ok(args),
+            Some('"') => loop {
+                match chars.next().ok_or(IncompleteQuote)? {
+                    '"' => break,
+                    '\\' => match chars.next().ok_or(IncompleteEscape)? {
+                        c @ ('"' | '`' | '$' | '\\') => arg.push(c),
+                        c => return Err(InvalidEscape(c)),
+                    },
+                    c @ ('`' | '$') => return Err(UnescapedChar(c)),
+                    c => arg.push(c),
                 }
-                ' ' if !in_quotes => {
-                    result.shrink_to_fit();
-                    return Some(Ok(result));
-                }
-                _ => {
-                    result.push(c);
-                    escaping = false;
+            },
+            Some(c) => {
+                let mut next = Some(c);
+                while let Some(c) = next {
+                    if RESERVED_CHARS.contains(c) {
+                        return Err(ReservedChar(c));
+                    }
+                    arg.push(c);
+                    next = chars.next_if(|&c| c != ' ' && c != '"');
                 }
             }
         }
-        if escaping {
-            Some(Err(IncompleteEscape))
-        } else if in_quotes {
-            Some(Err(IncompleteQuote))
-        } else {
-            result.shrink_to_fit();
-            Some(Ok(result))
+        // a quote can't start or end in the middle of an argument
+        if chars.peek().is_some_and(|&c| c != ' ') {
+            return Err(PartialQuote);
         }
+        args.push(arg);
     }
 }
 
@@ -85,54 +324,105 @@ struct ReplaceFlags<'a>(&'a ExecContext<'a>);
 
 impl<'a> regex::Replacer for ReplaceFlags<'a> {
     fn replace_append(&mut self, cap: &Captures, dst: &mut String) {
-        // FIXME? should we localize icon and name?
         match &cap[0] {
-            // FIXME: this is actually supposed to use seperate commands for each
-            // argument
-            "%f" | "%u" => {
+            // with more than one argument, each gets its own command,
+            // see `parse_commands`
+            "%f" => {
                 if let Some(f) = self.0.args.first() {
-                    dst.push_str(f);
+                    dst.push_str(&file_arg(f).to_string_lossy());
                 }
             }
-            "%i" => {
-                if let Some(Icon(i)) = self.0.source.get::<Icon>() {
-                    dst.push_str(&i);
+            "%u" => {
+                if let Some(u) = self.0.args.first() {
+                    dst.push_str(&url_arg(u));
                 }
             }
+            // only expanded as a whole argument, see `parse_command`
+            "%i" => {}
             "%c" => {
-                if let Some(Name(n)) = self.0.source.get::<Name>() {
-                    dst.push_str(&n);
-                }
-            }
-            "%k" => {
-                if let Some(ref p) = self.0.source_path {
-                    dst.push_str(p);
+                if let Some(ref n) = self.0.name {
+                    dst.push_str(n);
                 }
             }
+            "%k" => dst.push_str(&self.0.source.expand()),
             "%%" => dst.push('%'),
             _ => {} // unrecognized flag
         }
     }
 }
 
+/// Convert an argument for `%f` or `%F`, turning `file://` URIs into paths
+///
+/// Other URIs are passed unchanged.
+fn file_arg(arg: &str) -> OsString {
+    match uri::file_uri_to_path(arg) {
+        Some(path) => path.into_os_string(),
+        None => arg.into(),
+    }
+}
+
+/// Convert an argument for `%u` or `%U`, turning paths into `file://` URIs
+///
+/// An existing file is always treated as a path, even if its name looks
+/// like a URI.
+fn url_arg(arg: &str) -> String {
+    url_arg_in(arg, Path::new(""))
+}
+
+/// Like `url_arg`, but with relative paths resolved against `base`
+///
+/// An empty `base` is the current directory.
+fn url_arg_in(arg: &str, base: &Path) -> String {
+    let path = base.join(arg);
+    if uri::is_uri(arg) && !path.exists() {
+        arg.into()
+    } else {
+        uri::path_to_file_uri(path)
+    }
+}
+
+const FLAG_RE: &str = "%.";
+
+fn flag_re() -> &'static Regex {
+    static RE_CELL: OnceCell<Regex> = OnceCell::new();
+    // FLAG_RE is checked by the flag_re_test test
+    RE_CELL.get_or_init(|| Regex::new(FLAG_RE).expect("invalid FLAG_RE"))
+}
+
+/// Build the command for an `Exec` value
+///
+/// If the command uses `%f` or `%u`, only the first argument is passed. Use
+/// `parse_commands` to get a command for each argument instead.
 pub fn parse_command<'a>(command: &str, context: &ExecContext<'a>) -> Result<Command, Error> {
     use self::Error::*;
 
-    static FLAG_RE: OnceCell<Regex> = OnceCell::new();
-    let flag_re = FLAG_RE.get_or_init(|| Regex::new("%.").unwrap());
-
-    let mut words = split_command(command);
-    let bin = words.next().unwrap_or(Err(NoCommand))?;
+    let flag_re = flag_re();
+    let mut words = tokenize(command)?.into_iter();
+    let bin = words.next().ok_or(NoCommand)?;
     let mut command = Command::new(&bin);
+    if let Some(dir) = context.resolve_working_dir()? {
+        command.current_dir(dir);
+    }
     let mut had_file_or_url = false;
     for arg in words {
-        let arg = arg?;
         if arg == "%F" || arg == "%U" {
             if had_file_or_url {
                 return Err(MultipleFileArgs);
             }
-            command.args(context.args);
+            if arg == "%F" {
+                command.args(context.args.iter().map(|a| file_arg(a)));
+            } else {
+                command.args(context.args.iter().map(|a| url_arg(a)));
+            }
             had_file_or_url = true;
+        } else if (arg == "%f" || arg == "%u") && context.args.is_empty() {
+            // without a file or url, the argument is removed instead of
+            // being passed empty
+        } else if arg == "%i" {
+            // the icon is passed as two arguments, or none if there isn't one
+            if let Some(ref icon) = context.icon {
+                command.arg("--icon").arg(icon);
+            }
         } else {
             let replaced = flag_re.replace_all(&arg, ReplaceFlags(context));
             command.arg(replaced.as_ref());
@@ -141,38 +431,670 @@ pub fn parse_command<'a>(command: &str, context: &ExecContext<'a>) -> Result<Com
     Ok(command)
 }
 
-pub struct CommandExecutor<'a> {
-    entry: &'a DesktopEntry,
-    command: Command,
+/// Build the commands to run for an `Exec` value
+///
+/// The spec requires launching one instance per argument if the command
+/// uses `%f` or `%u` and more than one file or url is passed. Otherwise
+/// this is a single command, like `parse_command`.
+pub fn parse_commands(command: &str, context: &ExecContext) -> Result<Vec<Command>, Error> {
+    if context.args.len() > 1 && takes_single_arg(command)? {
+        context
+            .args
+            .iter()
+            .map(|arg| {
+                let single = ExecContext {
+                    args: slice::from_ref(arg),
+                    ..context.clone()
+                };
+                parse_command(command, &single)
+            })
+            .collect()
+    } else {
+        parse_command(command, context).map(|c| vec![c])
+    }
 }
 
-impl<'a> CommandExecutor<'a> {
+/// Check if a command uses `%f` or `%u`
+fn takes_single_arg(command: &str) -> Result<bool, Error> {
+    for word in tokenize(command)? {
+        if flag_re()
+            .find_iter(&word)
+            .any(|m| m.as_str() == "%f" || m.as_str() == "%u")
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Terminal emulators tried by `TerminalEmulator::detect`, in order of
+/// preference, with the arguments that make them run a command
+///
+/// The arguments come right before the command, so `--` means the
+/// remaining arguments are the command and its arguments.
+pub const KNOWN_TERMINALS: &[(&str, &[&str])] = &[
+    ("x-terminal-emulator", &["-e"]),
+    ("gnome-terminal", &["--"]),
+    ("kgx", &["--"]),
+    ("konsole", &["-e"]),
+    ("xfce4-terminal", &["-x"]),
+    ("mate-terminal", &["-x"]),
+    ("terminator", &["-x"]),
+    ("alacritty", &["-e"]),
+    ("kitty", &[]),
+    ("foot", &[]),
+    ("wezterm", &["start", "--"]),
+    ("urxvt", &["-e"]),
+    ("st", &["-e"]),
+    ("xterm", &["-e"]),
+];
+
+/// A terminal emulator, used to run commands of entries with `Terminal=true`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TerminalEmulator {
+    program: OsString,
+    /// Arguments between the program and the command, such as `-e`
+    exec_args: Vec<String>,
+}
+
+impl TerminalEmulator {
+    pub fn new(program: impl Into<OsString>, exec_args: &[&str]) -> TerminalEmulator {
+        TerminalEmulator {
+            program: program.into(),
+            exec_args: exec_args.iter().map(|&a| a.into()).collect(),
+        }
+    }
+
+    /// A terminal emulator given by name or path, such as `$TERMINAL`
+    ///
+    /// Emulators in `KNOWN_TERMINALS` use their own arguments, any other
+    /// emulator is assumed to accept `-e`.
+    pub fn from_program(program: impl Into<OsString>) -> TerminalEmulator {
+        let program = program.into();
+        let name = Path::new(&program).file_name().and_then(OsStr::to_str);
+        let exec_args = KNOWN_TERMINALS
+            .iter()
+            .find(|&&(known, _)| Some(known) == name)
+            .map_or(&["-e"][..], |&(_, args)| args);
+        TerminalEmulator::new(program, exec_args)
+    }
+
+    /// Find the terminal emulator to use
+    ///
+    /// This is `xdg-terminal-exec` if it is installed, as described by the
+    /// proposed xdg-terminal-exec spec, then the emulator named by
+    /// `$TERMINAL`, and otherwise the first of `KNOWN_TERMINALS` found in
+    /// `$PATH`.
+    pub fn detect() -> Option<TerminalEmulator> {
+        let preferences: Vec<_> = KNOWN_TERMINALS
+            .iter()
+            .map(|&(program, args)| TerminalEmulator::new(program, args))
+            .collect();
+        TerminalEmulator::detect_with(&preferences)
+    }
+
+    /// Like `detect`, but trying `preferences` instead of `KNOWN_TERMINALS`
+    pub fn detect_with(preferences: &[TerminalEmulator]) -> Option<TerminalEmulator> {
+        if find_program("xdg-terminal-exec").is_some() {
+            return Some(TerminalEmulator::new("xdg-terminal-exec", &[]));
+        }
+        if let Some(terminal) = env::var_os("TERMINAL").filter(|t| !t.is_empty()) {
+            return Some(TerminalEmulator::from_program(terminal));
+        }
+        preferences
+            .iter()
+            .find(|t| find_program(&t.program).is_some())
+            .cloned()
+    }
+
+    pub fn program(&self) -> &OsStr {
+        &self.program
+    }
+
+    pub fn exec_args(&self) -> &[String] {
+        &self.exec_args
+    }
+
+    /// Build a command that runs `command` in this terminal emulator
+    ///
+    /// The working directory and environment of `command` are kept.
+    pub fn wrap(&self, command: &Command) -> Command {
+        let mut wrapper = Command::new(&self.program);
+        wrapper.args(&self.exec_args);
+        wrap_command(wrapper, command)
+    }
+}
+
+/// Append `command` to the arguments of `wrapper`, which runs it
+///
+/// The working directory and environment of `command` are kept.
+fn wrap_command(mut wrapper: Command, command: &Command) -> Command {
+    wrapper.arg(command.get_program()).args(command.get_args());
+    if let Some(dir) = command.get_current_dir() {
+        wrapper.current_dir(dir);
+    }
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => wrapper.env(key, value),
+            None => wrapper.env_remove(key),
+        };
+    }
+    wrapper
+}
+
+/// Look up an executable in `$PATH`, unless it is already a path
+fn find_program(program: impl AsRef<Path>) -> Option<PathBuf> {
+    let program = program.as_ref();
+    if program.components().count() > 1 {
+        return Some(program.to_path_buf()).filter(|p| is_executable(p));
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|p| is_executable(p))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+impl DesktopEntry {
+    /// Check if the program in the `TryExec` key is installed
+    ///
+    /// The program is looked up in `$PATH` unless it is an absolute path.
+    /// The spec says entries whose `TryExec` program is missing should be
+    /// ignored. Entries without `TryExec` are always available.
+    pub fn try_exec_available(&self) -> bool {
+        match self.get::<TryExec>() {
+            Some(TryExec(program)) => find_program(program).is_some(),
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CommandExecutor {
+    commands: Vec<Command>,
+    desktop_id: Option<String>,
+}
+
+/// A process started by `CommandExecutor::spawn`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LaunchedApp {
+    pub pid: u32,
+    /// The desktop file ID of the entry, if it was read from a file
+    pub desktop_id: Option<String>,
+}
+
+impl CommandExecutor {
     pub fn new(
-        entry: &'a DesktopEntry,
-        args: &'a [String],
+        entry: &DesktopEntry,
+        args: &[String],
         path: Option<String>,
-    ) -> Result<CommandExecutor<'a>, Error> {
+    ) -> Result<CommandExecutor, Error> {
         let exec_str = entry.get::<Exec>().ok_or(Error::NoCommand)?;
-        let command = parse_command(
-            &exec_str,
-            &ExecContext {
-                source: entry,
-                source_path: path,
-                args,
-            },
-        )?;
-        Ok(CommandExecutor { entry, command })
+        CommandExecutor::with_context(&exec_str, &ExecContext::new(entry, args, path.into()))
+    }
+
+    /// Like `new`, but fail with `Error::TryExecMissing` if the entry's
+    /// `TryExec` program isn't installed
+    pub fn new_checked(
+        entry: &DesktopEntry,
+        args: &[String],
+        path: Option<String>,
+    ) -> Result<CommandExecutor, Error> {
+        match entry.get::<TryExec>() {
+            Some(TryExec(program)) if find_program(&program).is_none() => {
+                Err(Error::TryExecMissing(program))
+            }
+            _ => CommandExecutor::new(entry, args, path),
+        }
+    }
+
+    /// Executor for an `Exec` value other than the main one of the entry,
+    /// such as that of an action.
+    ///
+    /// If the context needs a terminal, one is found with
+    /// `TerminalEmulator::detect`.
+    pub fn with_context(exec: &str, context: &ExecContext) -> Result<CommandExecutor, Error> {
+        if !context.terminal {
+            let commands = parse_commands(exec, context)?;
+            return Ok(CommandExecutor {
+                commands,
+                desktop_id: context.desktop_id(),
+            });
+        }
+        let terminal = TerminalEmulator::detect().ok_or(Error::NoTerminal)?;
+        CommandExecutor::with_terminal(exec, context, &terminal)
+    }
+
+    /// Like `with_context`, but using `terminal` if the context needs one
+    pub fn with_terminal(
+        exec: &str,
+        context: &ExecContext,
+        terminal: &TerminalEmulator,
+    ) -> Result<CommandExecutor, Error> {
+        let mut commands = parse_commands(exec, context)?;
+        if context.terminal {
+            commands = commands.iter().map(|c| terminal.wrap(c)).collect();
+        }
+        Ok(CommandExecutor {
+            commands,
+            desktop_id: context.desktop_id(),
+        })
+    }
+
+    /// Start the commands in the background instead of replacing this process
+    ///
+    /// This is meant for launchers that keep running. On Unix each command
+    /// is detached like a daemon: it runs in a new session (`setsid`), so
+    /// it has no controlling terminal and doesn't get signals meant for the
+    /// launcher, such as Ctrl-C or a hangup when the terminal closes. It is
+    /// also started through a double fork, so it is reparented to init (or
+    /// the closest subreaper) and never becomes a zombie of the launcher.
+    /// The returned pid is that of the application itself.
+    pub fn spawn(self) -> Result<Vec<LaunchedApp>, Error> {
+        if self.commands.is_empty() {
+            return Err(Error::NoCommand);
+        }
+        let mut launched = Vec::with_capacity(self.commands.len());
+        for mut command in self.commands {
+            command.stdin(Stdio::null());
+            launched.push(LaunchedApp {
+                pid: spawn_detached(command).map_err(Error::ExecuteFailed)?,
+                desktop_id: self.desktop_id.clone(),
+            });
+        }
+        Ok(launched)
     }
 }
 
-impl<'a> Executor for CommandExecutor<'a> {
+/// Start `command` in a new session through a double fork, returning its pid
+///
+/// The intermediate child calls `setsid`, forks, sends the pid of the
+/// grandchild back through a pipe and exits. Only the grandchild execs the
+/// command, and errors from exec are still reported by `Command::spawn`.
+#[cfg(unix)]
+fn spawn_detached(mut command: Command) -> io::Result<u32> {
+    use std::fs::File;
+    use std::io::Read;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    let mut fds = [0; 2];
+    // SAFETY: fds has room for both ends of the pipe
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the pipe was just created and nothing else owns its ends
+    let (mut reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    let write_fd = writer.as_raw_fd();
+    // SAFETY: only async-signal-safe functions are called between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            match libc::fork() {
+                -1 => Err(io::Error::last_os_error()),
+                0 => Ok(()),
+                pid => {
+                    let bytes = pid.to_ne_bytes();
+                    libc::write(write_fd, bytes.as_ptr().cast(), bytes.len());
+                    libc::_exit(0)
+                }
+            }
+        });
+    }
+    let spawned = command.spawn();
+    drop(writer);
+    let mut child = spawned?;
+    let mut bytes = [0; std::mem::size_of::<libc::pid_t>()];
+    let read = reader.read_exact(&mut bytes);
+    // reap the intermediate child, which exits right after forking
+    child.wait()?;
+    read?;
+    Ok(libc::pid_t::from_ne_bytes(bytes) as u32)
+}
+
+/// Without `setsid`, just start `command` in the background
+#[cfg(not(unix))]
+fn spawn_detached(mut command: Command) -> io::Result<u32> {
+    command.spawn().map(|child| child.id())
+}
+
+impl Executor for CommandExecutor {
     fn execute(mut self) -> Result<(), Error> {
         // TODO: setup environment
-        self.command.exec();
-        Err(Error::ExecuteFailed)
+        // every command but the last is started in the background, the
+        // last replaces this process
+        let last = self.commands.pop().ok_or(Error::NoCommand)?;
+        for mut command in self.commands {
+            command.spawn().map_err(Error::ExecuteFailed)?;
+        }
+        exec(last)
     }
 }
 
+/// Replace the current process with `command`, which only returns if it failed
+#[cfg(unix)]
+fn exec(mut command: Command) -> Result<(), Error> {
+    Err(Error::ExecuteFailed(command.exec()))
+}
+
+/// Without `exec`, start `command` in the background and return
+#[cfg(not(unix))]
+fn exec(mut command: Command) -> Result<(), Error> {
+    command.spawn().map(drop).map_err(Error::ExecuteFailed)
+}
+
+/// Launch an entry, passing it `args`
+///
+/// The way it is launched is picked automatically, see `Launchers::new`.
 pub fn execute(entry: &DesktopEntry, args: &[String], path: Option<String>) -> Result<(), Error> {
-    CommandExecutor::new(entry, args, path).and_then(Executor::execute)
+    Launchers::new().launch(entry, &ExecContext::new(entry, args, path.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flag_re_test() {
+        assert!(Regex::new(FLAG_RE).is_ok());
+    }
+
+    #[test]
+    fn tokenize_test() {
+        let cases: &[(&str, &[&str])] = &[
+            ("", &[]),
+            ("app", &["app"]),
+            ("app  %U ", &["app", "%U"]),
+            ("app --name=foo %f", &["app", "--name=foo", "%f"]),
+            (r#""/opt/My App/app" %F"#, &["/opt/My App/app", "%F"]),
+            (
+                r#"sh -c "echo \"hi\" \$HOME \`date\` \\n""#,
+                &["sh", "-c", r#"echo "hi" $HOME `date` \n"#],
+            ),
+            (r#"app "" x"#, &["app", "", "x"]),
+            (r#"app "a'b;c|d""#, &["app", "a'b;c|d"]),
+            ("app 100%", &["app", "100%"]),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(tokenize(input).unwrap(), *expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn tokenize_errors_test() {
+        let cases = [
+            (r#"app "unterminated"#, "Unterminated quote in command"),
+            (r#"app "trailing\"#, "Incomplete escape sequence in command"),
+            (
+                r#"app "\a""#,
+                "Invalid escape sequence \\a in quoted argument",
+            ),
+            (
+                r#"app "$HOME""#,
+                "Character '$' must be escaped inside quotes",
+            ),
+            (
+                r#"app "a`b""#,
+                "Character '`' must be escaped inside quotes",
+            ),
+            (
+                r#"app --name="foo""#,
+                "Quotes must enclose a whole argument",
+            ),
+            (r#"app "foo"bar"#, "Quotes must enclose a whole argument"),
+            ("app $HOME", "Reserved character '$' must be quoted"),
+            ("app a;b", "Reserved character ';' must be quoted"),
+            ("app ~/file", "Reserved character '~' must be quoted"),
+            ("app a\\ b", "Reserved character '\\\\' must be quoted"),
+            ("app 'quoted'", "Reserved character '\\'' must be quoted"),
+            ("app\tx", "Reserved character '\\t' must be quoted"),
+            ("app > out", "Reserved character '>' must be quoted"),
+        ];
+        for (input, expected) in cases {
+            let err = tokenize(input).unwrap_err();
+            assert_eq!(err.to_string(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn file_and_url_args_test() {
+        let entry = crate::parse("[Desktop Entry]\n").unwrap();
+        let args = [
+            "/tmp/a b.txt".to_string(),
+            "https://example.com/".to_string(),
+        ];
+        let context = ExecContext::new(&entry, &args, EntrySource::None);
+        let command = parse_command("app %U", &context).unwrap();
+        let urls: Vec<_> = command.get_args().collect();
+        assert_eq!(urls, ["file:///tmp/a%20b.txt", "https://example.com/"]);
+
+        let args = ["file:///tmp/a%20b.txt".to_string()];
+        let context = ExecContext::new(&entry, &args, EntrySource::None);
+        let command = parse_command("app --file=%f", &context).unwrap();
+        let files: Vec<_> = command.get_args().collect();
+        assert_eq!(files, ["--file=/tmp/a b.txt"]);
+
+        // a relative path with a colon isn't a URI
+        let url = url_arg("notes:2024.txt");
+        assert!(url.starts_with("file:///"), "{}", url);
+        assert!(url.ends_with("/notes%3A2024.txt"), "{}", url);
+        assert_eq!(url_arg("mailto:me@example.com"), "mailto:me@example.com");
+
+        // neither is an existing file named like one
+        let dir = std::env::temp_dir().join(format!("dopen-url-arg-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("mailto:me"), "").unwrap();
+        let url = url_arg_in("mailto:me", &dir);
+        let other = url_arg_in("mailto:you", &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(url, uri::path_to_file_uri(dir.join("mailto:me")));
+        assert!(url.ends_with("/mailto%3Ame"), "{}", url);
+        assert_eq!(other, "mailto:you");
+    }
+
+    #[test]
+    fn parse_commands_test() {
+        let entry = crate::parse("[Desktop Entry]\n").unwrap();
+        let args = ["/tmp/a".to_string(), "/tmp/b".to_string()];
+        let context = ExecContext::new(&entry, &args, EntrySource::None);
+        let args_of = |commands: Vec<Command>| -> Vec<Vec<String>> {
+            commands
+                .iter()
+                .map(|c| c.get_args().map(|a| a.to_string_lossy().into()).collect())
+                .collect()
+        };
+
+        let commands = parse_commands("app --file=%f", &context).unwrap();
+        assert_eq!(args_of(commands), [["--file=/tmp/a"], ["--file=/tmp/b"]]);
+        let commands = parse_commands("app %u", &context).unwrap();
+        assert_eq!(args_of(commands), [["file:///tmp/a"], ["file:///tmp/b"]]);
+        let commands = parse_commands("app %F", &context).unwrap();
+        assert_eq!(args_of(commands), [["/tmp/a", "/tmp/b"]]);
+        let commands = parse_commands("app 100%%f", &context).unwrap();
+        assert_eq!(args_of(commands), [["100%f"]]);
+
+        let context = ExecContext::new(&entry, &args[..1], EntrySource::None);
+        let commands = parse_commands("app %f", &context).unwrap();
+        assert_eq!(args_of(commands), [["/tmp/a"]]);
+
+        // a field code that is a whole argument and expands to nothing is
+        // removed, while one inside an argument leaves the rest of it
+        let context = ExecContext::new(&entry, &[], EntrySource::None);
+        let commands = parse_commands("app %f --x", &context).unwrap();
+        assert_eq!(args_of(commands), [["--x"]]);
+        let commands = parse_commands("app %u", &context).unwrap();
+        assert_eq!(args_of(commands), [[] as [String; 0]]);
+        let commands = parse_commands("app --file=%f", &context).unwrap();
+        assert_eq!(args_of(commands), [["--file="]]);
+    }
+
+    #[test]
+    fn custom_context_test() {
+        let args = ["/tmp/x".to_string()];
+        let context = ExecContext::with_args(&args)
+            .name(Some("Terminal".into()))
+            .source(EntrySource::Path("/tmp/term.desktop".into()));
+        let command = parse_command("term --title=%c %i %k -e %f", &context).unwrap();
+        let words: Vec<_> = command.get_args().collect();
+        assert_eq!(
+            words,
+            ["--title=Terminal", "/tmp/term.desktop", "-e", "/tmp/x"]
+        );
+
+        let entry = crate::parse("[Desktop Entry]\nName=App\nIcon=app\n").unwrap();
+        let context = ExecContext::new(&entry, &[], EntrySource::None).icon(Some("other".into()));
+        let command = parse_command("app %c %i --x=%i", &context).unwrap();
+        let words: Vec<_> = command.get_args().collect();
+        assert_eq!(words, ["App", "--icon", "other", "--x="]);
+
+        let entry = crate::parse("[Desktop Entry]\nName=App\nName[de]=Anwendung\n").unwrap();
+        let context = ExecContext::with_locale(&entry, &[], EntrySource::None, Some("de_DE"));
+        let command = parse_command("app --title=%c", &context).unwrap();
+        let words: Vec<_> = command.get_args().collect();
+        assert_eq!(words, ["--title=Anwendung"]);
+        let context = ExecContext::with_locale(&entry, &[], EntrySource::None, None);
+        let command = parse_command("app --title=%c", &context).unwrap();
+        let words: Vec<_> = command.get_args().collect();
+        assert_eq!(words, ["--title=App"]);
+
+        let source = EntrySource::Uri("smb://server/share/app.desktop".into());
+        let context = ExecContext::with_args(&[]).source(source);
+        let command = parse_command("app --desktop-file=%k", &context).unwrap();
+        let words: Vec<_> = command.get_args().collect();
+        assert_eq!(words, ["--desktop-file=smb://server/share/app.desktop"]);
+    }
+
+    #[test]
+    fn terminal_test() {
+        let term = TerminalEmulator::from_program("/usr/bin/gnome-terminal");
+        assert_eq!(term.program(), "/usr/bin/gnome-terminal");
+        assert_eq!(term.exec_args(), ["--"]);
+        assert_eq!(
+            TerminalEmulator::from_program("my-term").exec_args(),
+            ["-e"]
+        );
+
+        let entry = crate::parse("[Desktop Entry]\nTerminal=true\n").unwrap();
+        let args = ["/tmp/a".to_string()];
+        let context = ExecContext::new(&entry, &args, EntrySource::None);
+        let term = TerminalEmulator::new("xterm", &["-e"]);
+        let executor = CommandExecutor::with_terminal("vim %f", &context, &term).unwrap();
+        let command = &executor.commands[0];
+        let words: Vec<_> = command.get_args().collect();
+        assert_eq!(command.get_program(), "xterm");
+        assert_eq!(words, ["-e", "vim", "/tmp/a"]);
+
+        let context = context.terminal(false);
+        let executor = CommandExecutor::with_terminal("vim %f", &context, &term).unwrap();
+        assert_eq!(executor.commands[0].get_program(), "vim");
+    }
+
+    #[test]
+    fn desktop_id_test() {
+        let source = EntrySource::Path("/usr/share/applications/kde/konsole.desktop".into());
+        assert_eq!(source.desktop_id().as_deref(), Some("kde-konsole.desktop"));
+        let source = EntrySource::Path("/tmp/applications/applications/a.desktop".into());
+        assert_eq!(source.desktop_id().as_deref(), Some("a.desktop"));
+        let source = EntrySource::Path("./foo.desktop".into());
+        assert_eq!(source.desktop_id().as_deref(), Some("foo.desktop"));
+        assert_eq!(
+            EntrySource::Uri("smb://x/a.desktop".into()).desktop_id(),
+            None
+        );
+        assert_eq!(EntrySource::None.desktop_id(), None);
+    }
+
+    #[test]
+    fn spawn_test() {
+        let entry = crate::parse("[Desktop Entry]\nExec=sleep 10\n").unwrap();
+        let path = "/usr/share/applications/sleep.desktop".to_string();
+        let launched = CommandExecutor::new(&entry, &[], Some(path))
+            .unwrap()
+            .spawn()
+            .unwrap();
+        assert_eq!(launched.len(), 1);
+        assert_eq!(launched[0].desktop_id.as_deref(), Some("sleep.desktop"));
+
+        #[cfg(unix)]
+        {
+            let pid = launched[0].pid as libc::pid_t;
+            // SAFETY: these only query and signal the process that was started
+            unsafe {
+                // the app is in its own session and isn't a child of ours
+                assert_ne!(libc::getsid(pid), -1);
+                assert_ne!(libc::getsid(pid), libc::getsid(0));
+                assert_eq!(libc::waitpid(pid, std::ptr::null_mut(), libc::WNOHANG), -1);
+                libc::kill(pid, libc::SIGTERM);
+            }
+        }
+
+        let entry = crate::parse("[Desktop Entry]\nExec=/nonexistent/dopen-app\n").unwrap();
+        let result = CommandExecutor::new(&entry, &[], None).unwrap().spawn();
+        assert!(matches!(result, Err(Error::ExecuteFailed(_))));
+    }
+
+    #[test]
+    fn try_exec_test() {
+        let entry = crate::parse("[Desktop Entry]\nExec=sh\nTryExec=sh\n").unwrap();
+        assert!(entry.try_exec_available());
+        assert!(CommandExecutor::new_checked(&entry, &[], None).is_ok());
+
+        let entry = crate::parse("[Desktop Entry]\nExec=app\n").unwrap();
+        assert!(entry.try_exec_available());
+
+        let input = "[Desktop Entry]\nExec=app\nTryExec=/nonexistent/dopen-app\n";
+        let entry = crate::parse(input).unwrap();
+        assert!(!entry.try_exec_available());
+        assert!(matches!(
+            CommandExecutor::new_checked(&entry, &[], None),
+            Err(Error::TryExecMissing(p)) if p == "/nonexistent/dopen-app"
+        ));
+    }
+
+    #[test]
+    fn working_dir_test() {
+        let dir = env::temp_dir();
+        let input = format!("[Desktop Entry]\nPath={}\n", dir.display());
+        let entry = crate::parse(&input).unwrap();
+        let context = ExecContext::new(&entry, &[], EntrySource::None);
+        let command = parse_command("app", &context).unwrap();
+        assert_eq!(command.get_current_dir(), Some(dir.as_path()));
+
+        let missing = dir.join("dopen-missing-dir");
+        let context = context.working_dir(Some(missing.clone()));
+        assert!(matches!(
+            parse_command("app", &context),
+            Err(Error::MissingWorkingDir(d)) if d == missing
+        ));
+        let context = context.missing_dir(MissingDir::Inherit);
+        let command = parse_command("app", &context).unwrap();
+        assert_eq!(command.get_current_dir(), None);
+
+        let context = context.working_dir(Some(".".into()));
+        let command = parse_command("app", &context).unwrap();
+        assert_eq!(command.get_current_dir(), Some(Path::new(".")));
+        assert!(matches!(
+            parse_command("app", &context.strict(true)),
+            Err(Error::RelativeWorkingDir(_))
+        ));
+
+        let entry = crate::parse("[Desktop Entry]\nPath=\n").unwrap();
+        let context = ExecContext::new(&entry, &[], EntrySource::None);
+        assert_eq!(
+            parse_command("app", &context).unwrap().get_current_dir(),
+            None
+        );
+    }
 }
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use zbus::blocking::Connection;
+use zbus::names::WellKnownName;
+use zbus::zvariant::{ObjectPath, Value};
+
+use super::super::entries::DBusActivatable;
+use super::super::model::DesktopEntry;
+use super::{url_arg, Error, ExecContext, Executor, Launch, Launcher};
+
+const APPLICATION_INTERFACE: &str = "org.freedesktop.Application";
+
+/// Launches an application with `DBusActivatable=true` through the
+/// `org.freedesktop.Application` interface
+///
+/// The bus name is the desktop file ID without the `.desktop` suffix.
+/// Without arguments the application is activated, otherwise the arguments
+/// are opened as URIs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DBusExecutor {
+    app_id: String,
+    action: Option<String>,
+    uris: Vec<String>,
+}
+
+impl DBusExecutor {
+    /// Executor for the application with the given desktop file ID
+    pub fn new(desktop_id: &str, args: &[String]) -> Result<DBusExecutor, Error> {
+        let app_id = desktop_id.strip_suffix(".desktop").unwrap_or(desktop_id);
+        WellKnownName::try_from(app_id).map_err(|e| Error::DBusFailed(e.into()))?;
+        Ok(DBusExecutor {
+            app_id: app_id.into(),
+            action: None,
+            uris: args.iter().map(|a| url_arg(a)).collect(),
+        })
+    }
+
+    /// Run one of the application's actions with `ActivateAction` instead
+    ///
+    /// Actions don't take arguments, so any URIs are ignored.
+    pub fn action(mut self, id: &str) -> DBusExecutor {
+        self.action = Some(id.into());
+        self
+    }
+
+    /// The object path the application is exported at, derived from its id
+    fn object_path(&self) -> String {
+        format!("/{}", self.app_id.replace('.', "/").replace('-', "_"))
+    }
+}
+
+impl Executor for DBusExecutor {
+    fn execute(self) -> Result<(), Error> {
+        let conn = Connection::session().map_err(Error::DBusFailed)?;
+        let path =
+            ObjectPath::try_from(self.object_path()).map_err(|e| Error::DBusFailed(e.into()))?;
+        let dest = Some(self.app_id.as_str());
+        let iface = Some(APPLICATION_INTERFACE);
+        // TODO: pass desktop-startup-id for startup notification
+        let platform_data: HashMap<&str, Value> = HashMap::new();
+        let reply = match (&self.action, self.uris.is_empty()) {
+            (Some(action), _) => {
+                let params: Vec<Value> = Vec::new();
+                let body = (action, params, platform_data);
+                conn.call_method(dest, &path, iface, "ActivateAction", &body)
+            }
+            (None, true) => conn.call_method(dest, &path, iface, "Activate", &(platform_data,)),
+            (None, false) => {
+                let body = (&self.uris, platform_data);
+                conn.call_method(dest, &path, iface, "Open", &body)
+            }
+        };
+        reply.map(drop).map_err(Error::DBusFailed)
+    }
+}
+
+/// Launches entries with `DBusActivatable=true` with a `DBusExecutor`
+///
+/// Only entries read from a file are supported, since the bus name comes
+/// from the desktop file ID.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DBusLauncher;
+
+impl Launcher for DBusLauncher {
+    fn supports(&self, entry: &DesktopEntry, context: &ExecContext) -> bool {
+        entry.get::<DBusActivatable>() == Some(DBusActivatable(true))
+            && context.desktop_id().is_some()
+    }
+
+    fn prepare(&self, _entry: &DesktopEntry, context: &ExecContext) -> Result<Launch, Error> {
+        let id = context.desktop_id().ok_or(Error::NoLauncher)?;
+        DBusExecutor::new(&id, context.args).map(Launch::DBus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dbus_executor_test() {
+        let args = ["/tmp/a b".to_string()];
+        let executor = DBusExecutor::new("org.example.My-App.desktop", &args).unwrap();
+        assert_eq!(executor.app_id, "org.example.My-App");
+        assert_eq!(executor.object_path(), "/org/example/My_App");
+        assert_eq!(executor.uris, ["file:///tmp/a%20b"]);
+        assert!(DBusExecutor::new("not-a-bus-name.desktop", &[]).is_err());
+    }
+}
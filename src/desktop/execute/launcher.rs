@@ -0,0 +1,502 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::super::entries::{Exec, Flatpak};
+use super::super::model::DesktopEntry;
+use super::super::xdg::BaseDirs;
+use super::{
+    find_program, tokenize, url_arg, wrap_command, CommandExecutor, Error, ExecContext, Executor,
+};
+
+/// A way of launching desktop entries
+///
+/// `Launchers` picks the first launcher that supports an entry.
+pub trait Launcher {
+    /// Check if this launcher can launch `entry`
+    fn supports(&self, entry: &DesktopEntry, context: &ExecContext) -> bool;
+
+    /// Prepare launching `entry`, which this launcher supports
+    ///
+    /// Nothing may be started yet, so `Launchers` can try the next launcher
+    /// if this fails.
+    fn prepare(&self, entry: &DesktopEntry, context: &ExecContext) -> Result<Launch, Error>;
+}
+
+/// A launch prepared by a `Launcher`, which runs when executed
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Launch {
+    Command(CommandExecutor),
+    #[cfg(feature = "dbus")]
+    DBus(super::DBusExecutor),
+}
+
+impl Executor for Launch {
+    fn execute(self) -> Result<(), Error> {
+        match self {
+            Launch::Command(executor) => executor.execute(),
+            #[cfg(feature = "dbus")]
+            Launch::DBus(executor) => executor.execute(),
+        }
+    }
+}
+
+impl From<CommandExecutor> for Launch {
+    fn from(executor: CommandExecutor) -> Launch {
+        Launch::Command(executor)
+    }
+}
+
+/// Runs the `Exec` command of the entry
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExecLauncher;
+
+impl Launcher for ExecLauncher {
+    fn supports(&self, entry: &DesktopEntry, _context: &ExecContext) -> bool {
+        entry.get::<Exec>().is_some()
+    }
+
+    fn prepare(&self, entry: &DesktopEntry, context: &ExecContext) -> Result<Launch, Error> {
+        let exec = entry.get::<Exec>().ok_or(Error::NoCommand)?;
+        CommandExecutor::with_context(&exec, context).map(Launch::from)
+    }
+}
+
+/// Runs the `Exec` command in a new transient scope with
+/// `systemd-run --user --scope`
+///
+/// This gives every application its own cgroup, named like the systemd
+/// desktop environment conventions describe, so resource usage can be
+/// tracked and limited per application.
+#[derive(Debug, Clone)]
+pub struct SystemdRunLauncher {
+    runtime_dir: Option<PathBuf>,
+}
+
+impl SystemdRunLauncher {
+    /// Launcher for the user manager of the current process
+    pub fn from_env() -> SystemdRunLauncher {
+        SystemdRunLauncher::with_dirs(&BaseDirs::from_env())
+    }
+
+    /// Launcher for the user manager found in `dirs.runtime_dir`
+    pub fn with_dirs(dirs: &BaseDirs) -> SystemdRunLauncher {
+        SystemdRunLauncher {
+            runtime_dir: dirs.runtime_dir.clone(),
+        }
+    }
+}
+
+impl Default for SystemdRunLauncher {
+    fn default() -> SystemdRunLauncher {
+        SystemdRunLauncher::from_env()
+    }
+}
+
+impl Launcher for SystemdRunLauncher {
+    /// Supported if `systemd-run` is installed and a user manager is running
+    fn supports(&self, entry: &DesktopEntry, _context: &ExecContext) -> bool {
+        let user_manager = self
+            .runtime_dir
+            .as_ref()
+            .is_some_and(|dir| dir.join("systemd").is_dir());
+        user_manager && entry.get::<Exec>().is_some() && find_program("systemd-run").is_some()
+    }
+
+    fn prepare(&self, entry: &DesktopEntry, context: &ExecContext) -> Result<Launch, Error> {
+        let exec = entry.get::<Exec>().ok_or(Error::NoCommand)?;
+        let mut executor = CommandExecutor::with_context(&exec, context)?;
+        let app_id = executor.desktop_id.as_deref().map(|id| {
+            let id = id.strip_suffix(".desktop").unwrap_or(id);
+            systemd_escape(id)
+        });
+        for (n, command) in executor.commands.iter_mut().enumerate() {
+            let mut wrapper = Command::new("systemd-run");
+            wrapper.args(["--user", "--scope", "--quiet", "--collect"]);
+            if let Some(ref app_id) = app_id {
+                wrapper.arg(format!(
+                    "--unit=app-dopen-{}-{}.scope",
+                    app_id,
+                    unit_suffix(n)
+                ));
+            }
+            wrapper.arg("--");
+            *command = wrap_command(wrapper, command);
+        }
+        Ok(executor.into())
+    }
+}
+
+/// Escape a string for use in a unit name, like `systemd-escape`
+fn systemd_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for (i, b) in s.bytes().enumerate() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b':' => escaped.push(b as char),
+            b'.' if i > 0 => escaped.push('.'),
+            _ => write!(escaped, "\\x{:02x}", b).unwrap(),
+        }
+    }
+    escaped
+}
+
+/// A suffix that makes the scope name of each launch unique
+fn unit_suffix(n: usize) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    format!("{:x}{:08x}{}", process::id(), nanos, n)
+}
+
+/// Runs entries exported by Flatpak with `flatpak run`
+///
+/// Flatpak entries have an `X-Flatpak` key with the application ID. The
+/// `Exec` key of an exported entry already runs `flatpak run` with the
+/// right `--command`, `--branch` and `--arch`, and forwards files between
+/// `@@u` and `@@`, so it is used as it is. If the entry has no `Exec`, or
+/// it doesn't run `flatpak` for that application, a command is built from
+/// the application ID instead, forwarding the arguments as URIs, which
+/// gives the sandbox access to local files.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlatpakLauncher;
+
+impl Launcher for FlatpakLauncher {
+    fn supports(&self, entry: &DesktopEntry, _context: &ExecContext) -> bool {
+        entry.get::<Flatpak>().is_some() && find_program("flatpak").is_some()
+    }
+
+    fn prepare(&self, entry: &DesktopEntry, context: &ExecContext) -> Result<Launch, Error> {
+        let Flatpak(app_id) = entry.get::<Flatpak>().ok_or(Error::NoCommand)?;
+        if let Some(exec) = entry.get::<Exec>().filter(|e| runs_flatpak(e, &app_id)) {
+            return CommandExecutor::with_context(&exec, context).map(Launch::from);
+        }
+        let mut command = Command::new("flatpak");
+        command.arg("run");
+        if !context.args.is_empty() {
+            command.arg("--file-forwarding").arg(&app_id).arg("@@u");
+            command.args(context.args.iter().map(|a| url_arg(a)));
+            command.arg("@@");
+        } else {
+            command.arg(&app_id);
+        }
+        Ok(Launch::Command(CommandExecutor {
+            commands: vec![command],
+            desktop_id: context.desktop_id(),
+        }))
+    }
+}
+
+/// Check if an `Exec` command is `flatpak run` for `app_id`
+fn runs_flatpak(exec: &str, app_id: &str) -> bool {
+    let Ok(args) = tokenize(exec) else {
+        return false;
+    };
+    let is_flatpak = args
+        .first()
+        .is_some_and(|program| Path::new(program).file_name() == Some("flatpak".as_ref()));
+    is_flatpak && args.get(1).map(String::as_str) == Some("run") && args.iter().any(|a| a == app_id)
+}
+
+/// The launchers to try, in order of preference
+pub struct Launchers(Vec<Box<dyn Launcher>>);
+
+impl Launchers {
+    /// The default launchers, each used where it applies
+    ///
+    /// In order, these are:
+    ///
+    /// - D-Bus activation with the `dbus` feature, for `DBusActivatable`
+    ///   entries
+    /// - `FlatpakLauncher`, for entries with `X-Flatpak`. Flatpak puts the
+    ///   application in a scope of its own, so it goes before systemd-run.
+    /// - `SystemdRunLauncher`, when a systemd user manager is running
+    /// - plain `Exec`
+    ///
+    /// To override the choice, start from `empty` or add launchers with
+    /// `prefer`.
+    pub fn new() -> Launchers {
+        let launchers = Launchers::empty();
+        #[cfg(feature = "dbus")]
+        let launchers = launchers.push(super::DBusLauncher);
+        launchers
+            .push(FlatpakLauncher)
+            .push(SystemdRunLauncher::from_env())
+            .push(ExecLauncher)
+    }
+
+    /// No launchers, to build a custom list with `push`
+    pub fn empty() -> Launchers {
+        Launchers(Vec::new())
+    }
+
+    /// Add a launcher to try after the existing ones
+    pub fn push<L: Launcher + 'static>(mut self, launcher: L) -> Launchers {
+        self.0.push(Box::new(launcher));
+        self
+    }
+
+    /// Add a launcher to try before the existing ones
+    pub fn prefer<L: Launcher + 'static>(mut self, launcher: L) -> Launchers {
+        self.0.insert(0, Box::new(launcher));
+        self
+    }
+
+    /// Prepare launching `entry` with the first launcher that supports it
+    ///
+    /// If that launcher can't prepare the launch, the next one that supports
+    /// the entry is tried, and the last error is returned if none of them
+    /// work.
+    pub fn prepare(&self, entry: &DesktopEntry, context: &ExecContext) -> Result<Launch, Error> {
+        Launchers::prepare_next(&mut self.supporting(entry, context), entry, context)
+    }
+
+    /// Launch `entry` with the first launcher that can prepare it
+    ///
+    /// Once the launch is prepared, no other launcher is tried if running it
+    /// fails, since some commands may already have started. The exception is
+    /// D-Bus activation, which starts nothing if the call fails, so the next
+    /// launcher, usually `ExecLauncher`, is tried.
+    pub fn launch(&self, entry: &DesktopEntry, context: &ExecContext) -> Result<(), Error> {
+        Launchers::launch_next(&mut self.supporting(entry, context), entry, context)
+    }
+
+    fn supporting<'l>(
+        &'l self,
+        entry: &'l DesktopEntry,
+        context: &'l ExecContext,
+    ) -> impl Iterator<Item = &'l dyn Launcher> {
+        self.0
+            .iter()
+            .map(|l| &**l)
+            .filter(move |l| l.supports(entry, context))
+    }
+
+    fn prepare_next<'l>(
+        launchers: &mut impl Iterator<Item = &'l dyn Launcher>,
+        entry: &DesktopEntry,
+        context: &ExecContext,
+    ) -> Result<Launch, Error> {
+        let mut error = Error::NoLauncher;
+        for launcher in launchers {
+            match launcher.prepare(entry, context) {
+                Ok(launch) => return Ok(launch),
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    fn launch_next<'l>(
+        launchers: &mut impl Iterator<Item = &'l dyn Launcher>,
+        entry: &DesktopEntry,
+        context: &ExecContext,
+    ) -> Result<(), Error> {
+        match Launchers::prepare_next(launchers, entry, context)? {
+            #[cfg(feature = "dbus")]
+            Launch::DBus(executor) => match executor.execute() {
+                Err(error @ Error::DBusFailed(_)) => {
+                    match Launchers::launch_next(launchers, entry, context) {
+                        Err(Error::NoLauncher) => Err(error),
+                        result => result,
+                    }
+                }
+                result => result,
+            },
+            launch => launch.execute(),
+        }
+    }
+}
+
+impl Default for Launchers {
+    fn default() -> Launchers {
+        Launchers::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute::EntrySource;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Recording {
+        name: &'static str,
+        result: fn() -> Result<Launch, Error>,
+        log: Rc<RefCell<Vec<&'static str>>>,
+    }
+
+    impl Launcher for Recording {
+        fn supports(&self, _entry: &DesktopEntry, _context: &ExecContext) -> bool {
+            self.name != "unsupported"
+        }
+
+        fn prepare(&self, _entry: &DesktopEntry, _context: &ExecContext) -> Result<Launch, Error> {
+            self.log.borrow_mut().push(self.name);
+            (self.result)()
+        }
+    }
+
+    fn missing_program() -> Result<Launch, Error> {
+        Ok(Launch::Command(CommandExecutor {
+            commands: vec![Command::new("/nonexistent/dopen-test-program")],
+            desktop_id: None,
+        }))
+    }
+
+    #[test]
+    fn launchers_test() {
+        let entry = crate::parse("[Desktop Entry]\n").unwrap();
+        let context = ExecContext::new(&entry, &[], EntrySource::None);
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let recording = |name, result| Recording {
+            name,
+            result,
+            log: log.clone(),
+        };
+        let launchers = Launchers::empty()
+            .push(recording("ok", missing_program))
+            .push(recording("never", missing_program))
+            .prefer(recording("failing", || Err(Error::NoCommand)))
+            .prefer(recording("unsupported", missing_program));
+        // running the prepared launch fails, but isn't retried
+        let result = launchers.launch(&entry, &context);
+        assert!(matches!(result, Err(Error::ExecuteFailed(_))));
+        assert_eq!(*log.borrow(), ["failing", "ok"]);
+
+        let result = Launchers::empty().launch(&entry, &context);
+        assert!(matches!(result, Err(Error::NoLauncher)));
+        // the entry has no Exec, so nothing supports it
+        assert!(matches!(
+            Launchers::new().launch(&entry, &context),
+            Err(Error::NoLauncher)
+        ));
+    }
+
+    #[cfg(feature = "dbus")]
+    #[test]
+    fn dbus_fallback_test() {
+        let entry = crate::parse("[Desktop Entry]\n").unwrap();
+        let context = ExecContext::new(&entry, &[], EntrySource::None);
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let recording = |name, result| Recording {
+            name,
+            result,
+            log: log.clone(),
+        };
+        let missing_app = || {
+            super::super::DBusExecutor::new("org.example.DopenMissing.desktop", &[])
+                .map(Launch::DBus)
+        };
+        // nothing owns the bus name, so activation fails and Exec is tried
+        let launchers = Launchers::empty()
+            .push(recording("dbus", missing_app))
+            .push(recording("ok", missing_program));
+        let result = launchers.launch(&entry, &context);
+        assert!(matches!(result, Err(Error::ExecuteFailed(_))));
+        assert_eq!(*log.borrow(), ["dbus", "ok"]);
+
+        let launchers = Launchers::empty().push(recording("dbus", missing_app));
+        let result = launchers.launch(&entry, &context);
+        assert!(matches!(result, Err(Error::DBusFailed(_))));
+    }
+
+    #[test]
+    fn flatpak_test() {
+        let entry = crate::parse(
+            "\
+[Desktop Entry]
+Type=Application
+Name=LibreOffice Writer
+Exec=/usr/bin/flatpak run --branch=stable --arch=x86_64 --command=libreoffice --file-forwarding org.libreoffice.LibreOffice --writer @@u %U @@
+X-Flatpak=org.libreoffice.LibreOffice
+",
+        )
+        .unwrap();
+        let args = ["/tmp/a b.odt".to_string()];
+        let context = ExecContext::new(&entry, &args, EntrySource::None);
+        let Ok(Launch::Command(executor)) = FlatpakLauncher.prepare(&entry, &context) else {
+            panic!("expected a command");
+        };
+        let command = &executor.commands[0];
+        assert_eq!(command.get_program(), "/usr/bin/flatpak");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "run",
+                "--branch=stable",
+                "--arch=x86_64",
+                "--command=libreoffice",
+                "--file-forwarding",
+                "org.libreoffice.LibreOffice",
+                "--writer",
+                "@@u",
+                "file:///tmp/a%20b.odt",
+                "@@",
+            ]
+        );
+
+        // without Exec, the command is built from the application ID
+        let entry = crate::parse("[Desktop Entry]\nX-Flatpak=org.example.App\n").unwrap();
+        let context = ExecContext::new(&entry, &[], EntrySource::None);
+        let Ok(Launch::Command(executor)) = FlatpakLauncher.prepare(&entry, &context) else {
+            panic!("expected a command");
+        };
+        let args: Vec<_> = executor.commands[0].get_args().collect();
+        assert_eq!(args, ["run", "org.example.App"]);
+
+        // nor with an Exec that runs something other than the application
+        let entry =
+            crate::parse("[Desktop Entry]\nExec=/usr/bin/app %U\nX-Flatpak=org.example.App\n")
+                .unwrap();
+        let args = ["/tmp/a".to_string()];
+        let context = ExecContext::new(&entry, &args, EntrySource::None);
+        let Ok(Launch::Command(executor)) = FlatpakLauncher.prepare(&entry, &context) else {
+            panic!("expected a command");
+        };
+        assert_eq!(executor.commands[0].get_program(), "flatpak");
+        let args: Vec<_> = executor.commands[0].get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "run",
+                "--file-forwarding",
+                "org.example.App",
+                "@@u",
+                "file:///tmp/a",
+                "@@"
+            ]
+        );
+        assert!(!runs_flatpak(
+            "flatpak run org.example.Other",
+            "org.example.App"
+        ));
+        assert!(runs_flatpak(
+            "/usr/bin/flatpak run org.example.App",
+            "org.example.App"
+        ));
+    }
+
+    #[test]
+    fn systemd_run_supports_test() {
+        let entry = crate::parse("[Desktop Entry]\nExec=app\n").unwrap();
+        let context = ExecContext::new(&entry, &[], EntrySource::None);
+        let dirs = BaseDirs {
+            runtime_dir: Some("/nonexistent/dopen-runtime".into()),
+            ..BaseDirs::default()
+        };
+        // no user manager is running in that runtime directory
+        assert!(!SystemdRunLauncher::with_dirs(&dirs).supports(&entry, &context));
+        assert!(!SystemdRunLauncher::with_dirs(&BaseDirs::default()).supports(&entry, &context));
+    }
+
+    #[test]
+    fn systemd_escape_test() {
+        assert_eq!(systemd_escape("org.gnome.Terminal"), "org.gnome.Terminal");
+        assert_eq!(systemd_escape("kde-konsole"), "kde\\x2dkonsole");
+        assert_eq!(systemd_escape(".hidden app"), "\\x2ehidden\\x20app");
+    }
+}
@@ -0,0 +1,208 @@
+//! Reading of legacy mailcap files (RFC 1524)
+//!
+//! This is meant as a lowest priority source of handlers for users coming
+//! from `run-mailcap`, so invalid lines are skipped rather than reported.
+use std::fs;
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+
+use super::xdg::BaseDirs;
+
+/// A single rule from a mailcap file
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MailcapEntry {
+    /// The mime type, which may be a wildcard such as `image/*`
+    pub mime_type: String,
+    /// The shell command, with `%s` standing for the file name
+    pub command: String,
+    /// The command must be run in a terminal
+    pub needs_terminal: bool,
+    /// The command produces output that should be paged
+    pub copious_output: bool,
+    /// A shell command that must succeed for the rule to apply
+    pub test: Option<String>,
+}
+
+impl MailcapEntry {
+    /// Check if this rule applies to a mime type
+    pub fn matches(&self, mime_type: &str) -> bool {
+        let (major, _) = mime_type.split_once('/').unwrap_or((mime_type, ""));
+        // a bare major type is the same as a wildcard
+        match self.mime_type.split_once('/') {
+            Some((m, "*")) => m.eq_ignore_ascii_case(major),
+            Some(_) => self.mime_type.eq_ignore_ascii_case(mime_type),
+            None => self.mime_type.eq_ignore_ascii_case(major),
+        }
+    }
+}
+
+/// The rules from one or more mailcap files, in priority order
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Mailcap(Vec<MailcapEntry>);
+
+impl Mailcap {
+    /// Read `~/.mailcap` followed by `/etc/mailcap`
+    ///
+    /// Missing files are ignored.
+    pub fn load() -> io::Result<Mailcap> {
+        Mailcap::load_from(&BaseDirs::from_env())
+    }
+
+    /// Like `load`, but with the home directory from `dirs`
+    pub fn load_from(dirs: &BaseDirs) -> io::Result<Mailcap> {
+        let mut paths = Vec::new();
+        if let Some(ref home) = dirs.home {
+            paths.push(home.join(".mailcap"));
+        }
+        paths.push(PathBuf::from("/etc/mailcap"));
+        let mut mailcap = Mailcap::default();
+        for path in paths {
+            match mailcap.read_file(&path) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                res => res?,
+            }
+        }
+        Ok(mailcap)
+    }
+
+    /// Add the rules from a file, after any that are already present
+    pub fn read_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let content = fs::read(path)?;
+        self.0.extend(parse(&String::from_utf8_lossy(&content)).0);
+        Ok(())
+    }
+
+    /// Get the rules that apply to a mime type, in priority order
+    pub fn get<'a>(&'a self, mime_type: &'a str) -> impl Iterator<Item = &'a MailcapEntry> + 'a {
+        self.0.iter().filter(move |e| e.matches(mime_type))
+    }
+
+    /// Get an iterator over all rules
+    pub fn entries(&self) -> std::slice::Iter<'_, MailcapEntry> {
+        self.0.iter()
+    }
+}
+
+/// Parse the contents of a mailcap file
+pub fn parse(input: &str) -> Mailcap {
+    let mut entries = Vec::new();
+    let mut logical = String::new();
+    for line in input.lines() {
+        // a trailing backslash continues the rule on the next line
+        if let Some(start) = line.strip_suffix('\\') {
+            logical.push_str(start);
+            continue;
+        }
+        logical.push_str(line);
+        let trimmed = logical.trim();
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            entries.extend(parse_rule(trimmed));
+        }
+        logical.clear();
+    }
+    Mailcap(entries)
+}
+
+fn parse_rule(line: &str) -> Option<MailcapEntry> {
+    let mut fields = split_fields(line).into_iter();
+    let mime_type = fields.next().filter(|m| !m.is_empty())?;
+    let command = fields.next()?;
+    let mut entry = MailcapEntry {
+        mime_type,
+        command,
+        needs_terminal: false,
+        copious_output: false,
+        test: None,
+    };
+    for field in fields {
+        match field.split_once('=') {
+            Some((key, value)) if key.trim().eq_ignore_ascii_case("test") => {
+                entry.test = Some(value.trim().into());
+            }
+            Some(_) => {}
+            None if field.eq_ignore_ascii_case("needsterminal") => entry.needs_terminal = true,
+            None if field.eq_ignore_ascii_case("copiousoutput") => entry.copious_output = true,
+            None => {}
+        }
+    }
+    Some(entry)
+}
+
+/// Split on semicolons, keeping `\;` as a literal semicolon
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(';') => field.push(';'),
+                Some(c) => {
+                    field.push('\\');
+                    field.push(c);
+                }
+                None => field.push('\\'),
+            },
+            ';' => fields.push(mem::take(&mut field).trim().to_owned()),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_owned());
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let mailcap = parse(
+            "\
+# comment
+text/html; w3m -T text/html %s; needsterminal
+image/*; feh %s; test=test -n \"$DISPLAY\"
+application/pdf; zathura %s \\
+    2>/dev/null
+text/plain; echo a\\;b; copiousoutput
+not a rule
+",
+        );
+        let html: Vec<_> = mailcap.get("text/html").collect();
+        assert_eq!(html.len(), 1);
+        assert_eq!(html[0].command, "w3m -T text/html %s");
+        assert!(html[0].needs_terminal);
+
+        let png: Vec<_> = mailcap.get("image/png").collect();
+        assert_eq!(png[0].command, "feh %s");
+        assert_eq!(png[0].test.as_deref(), Some("test -n \"$DISPLAY\""));
+
+        let pdf: Vec<_> = mailcap.get("application/pdf").collect();
+        assert_eq!(pdf[0].command, "zathura %s     2>/dev/null");
+
+        let text: Vec<_> = mailcap.get("text/plain").collect();
+        assert_eq!(text[0].command, "echo a;b");
+        assert!(text[0].copious_output);
+
+        assert_eq!(mailcap.entries().count(), 4);
+    }
+
+    #[test]
+    fn load_from_test() {
+        let home = std::env::temp_dir().join(format!("dopen-mailcap-{}", std::process::id()));
+        fs::create_dir_all(&home).unwrap();
+        fs::write(home.join(".mailcap"), "x-dopen/test; dopen-viewer %s\n").unwrap();
+        let dirs = BaseDirs {
+            home: Some(home.clone()),
+            ..BaseDirs::default()
+        };
+        let mailcap = Mailcap::load_from(&dirs);
+        fs::remove_dir_all(&home).unwrap();
+        let mailcap = mailcap.unwrap();
+        // the user's rules come before those in /etc/mailcap
+        let first = mailcap.entries().next().unwrap();
+        assert_eq!(first.mime_type, "x-dopen/test");
+        assert_eq!(first.command, "dopen-viewer %s");
+    }
+}
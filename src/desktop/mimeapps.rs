@@ -0,0 +1,116 @@
+//! Parsing of `mimeapps.list` files
+//!
+//! See the [MIME Applications Associations spec](https://specifications.freedesktop.org/mime-apps-spec/latest/)
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::entries::util::split_value_str;
+use super::error::ParseError;
+use super::model::DesktopEntry;
+use super::parser::ParseOptions;
+
+pub const DEFAULT_APPLICATIONS: &str = "Default Applications";
+pub const ADDED_ASSOCIATIONS: &str = "Added Associations";
+pub const REMOVED_ASSOCIATIONS: &str = "Removed Associations";
+
+/// The associations from a single `mimeapps.list` file
+///
+/// Desktop file IDs for each mime type are kept in the order they appear
+/// in the file, which is the order of preference.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct MimeAppsList {
+    default: HashMap<String, Vec<String>>,
+    added: HashMap<String, Vec<String>>,
+    removed: HashMap<String, Vec<String>>,
+}
+
+impl MimeAppsList {
+    pub fn new() -> MimeAppsList {
+        MimeAppsList::default()
+    }
+
+    /// Collect the associations from a parsed file
+    ///
+    /// Groups other than the three defined by the spec are ignored.
+    pub fn from_entry(entry: &DesktopEntry) -> MimeAppsList {
+        let mut list = MimeAppsList::new();
+        for group in entry.groups() {
+            let map = match group.name() {
+                DEFAULT_APPLICATIONS => &mut list.default,
+                ADDED_ASSOCIATIONS => &mut list.added,
+                REMOVED_ASSOCIATIONS => &mut list.removed,
+                _ => continue,
+            };
+            for (mime, ids) in group.values() {
+                let ids = split_value_str(ids).filter(|id| !id.is_empty());
+                map.insert(mime.clone(), ids.collect());
+            }
+        }
+        list
+    }
+
+    /// Desktop file IDs of the default applications for a mime type
+    pub fn default_apps(&self, mime: &str) -> &[String] {
+        lookup(&self.default, mime)
+    }
+
+    /// Desktop file IDs explicitly associated with a mime type
+    pub fn added(&self, mime: &str) -> &[String] {
+        lookup(&self.added, mime)
+    }
+
+    /// Desktop file IDs that should not be associated with a mime type
+    pub fn removed(&self, mime: &str) -> &[String] {
+        lookup(&self.removed, mime)
+    }
+}
+
+fn lookup<'a>(map: &'a HashMap<String, Vec<String>>, mime: &str) -> &'a [String] {
+    map.get(mime).map_or(&[], Vec::as_slice)
+}
+
+/// Parse the contents of a `mimeapps.list` file
+pub fn parse<T: AsRef<[u8]>>(input: T) -> Result<MimeAppsList, ParseError> {
+    let entry = ParseOptions::new().mime_keys(true).parse(input)?;
+    Ok(MimeAppsList::from_entry(&entry))
+}
+
+/// Parse a `mimeapps.list` file
+pub fn parse_file<T: AsRef<Path>>(path: T) -> Result<MimeAppsList, ParseError> {
+    let entry = ParseOptions::new().mime_keys(true).parse_file(path)?;
+    Ok(MimeAppsList::from_entry(&entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let input = "\
+[Default Applications]
+text/html=firefox.desktop;chromium.desktop;
+image/svg+xml=inkscape.desktop
+
+[Added Associations]
+x-scheme-handler/http=firefox.desktop;
+application/vnd.ms-excel=libreoffice-calc.desktop;
+
+[Removed Associations]
+text/plain=emacs.desktop;
+";
+        let list = parse(input).unwrap();
+        assert_eq!(
+            list.default_apps("text/html"),
+            ["firefox.desktop", "chromium.desktop"]
+        );
+        assert_eq!(list.default_apps("image/svg+xml"), ["inkscape.desktop"]);
+        assert_eq!(list.added("x-scheme-handler/http"), ["firefox.desktop"]);
+        assert_eq!(
+            list.added("application/vnd.ms-excel"),
+            ["libreoffice-calc.desktop"]
+        );
+        assert_eq!(list.removed("text/plain"), ["emacs.desktop"]);
+        assert!(list.default_apps("text/plain").is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+//! Parsing of the `mimeinfo.cache` files written by `update-desktop-database`
+//!
+//! The cache maps each mime type to the desktop files that declare it in
+//! their `MimeType` key, so handlers can be found without reading every
+//! desktop file in an `applications` directory.
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::entries::util::split_value_str;
+use super::error::ParseError;
+use super::model::DesktopEntry;
+use super::parser::ParseOptions;
+
+pub const MIME_CACHE: &str = "MIME Cache";
+
+/// The contents of a single `mimeinfo.cache` file
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct MimeCache(HashMap<String, Vec<String>>);
+
+impl MimeCache {
+    pub fn new() -> MimeCache {
+        MimeCache::default()
+    }
+
+    /// Collect the mime types from a parsed file
+    pub fn from_entry(entry: &DesktopEntry) -> MimeCache {
+        let mut cache = MimeCache::new();
+        for group in entry.groups().filter(|g| g.name() == MIME_CACHE) {
+            for (mime, ids) in group.values() {
+                let ids = split_value_str(ids).filter(|id| !id.is_empty());
+                cache.0.insert(mime.clone(), ids.collect());
+            }
+        }
+        cache
+    }
+
+    /// Desktop file IDs of the applications that handle a mime type
+    pub fn get(&self, mime: &str) -> &[String] {
+        self.0.get(mime).map_or(&[], Vec::as_slice)
+    }
+
+    /// Iterate over all mime types in the cache
+    pub fn mime_types(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+}
+
+/// Parse the contents of a `mimeinfo.cache` file
+pub fn parse<T: AsRef<[u8]>>(input: T) -> Result<MimeCache, ParseError> {
+    let entry = ParseOptions::new().mime_keys(true).parse(input)?;
+    Ok(MimeCache::from_entry(&entry))
+}
+
+/// Parse a `mimeinfo.cache` file
+pub fn parse_file<T: AsRef<Path>>(path: T) -> Result<MimeCache, ParseError> {
+    let entry = ParseOptions::new().mime_keys(true).parse_file(path)?;
+    Ok(MimeCache::from_entry(&entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let input = "\
+[MIME Cache]
+application/pdf=org.gnome.Evince.desktop;okular_pdf.desktop;
+image/svg+xml=inkscape.desktop;
+";
+        let cache = parse(input).unwrap();
+        assert_eq!(
+            cache.get("application/pdf"),
+            ["org.gnome.Evince.desktop", "okular_pdf.desktop"]
+        );
+        assert_eq!(cache.get("image/svg+xml"), ["inkscape.desktop"]);
+        assert!(cache.get("text/plain").is_empty());
+        assert_eq!(cache.mime_types().count(), 2);
+    }
+}
@@ -1,10 +1,35 @@
 pub mod entries;
 pub mod execute;
+#[cfg(feature = "mailcap")]
+pub mod mailcap;
+pub mod mimeapps;
+#[cfg_attr(not(feature = "unstable"), doc(hidden))]
+pub mod mimecache;
+#[cfg_attr(not(feature = "unstable"), doc(hidden))]
+pub mod uri;
+pub mod xdg;
 
+mod action;
+mod builder;
+mod descriptor;
+mod deserialize;
+mod diff;
+mod document;
 mod error;
 mod model;
 mod parser;
+mod validate;
 
+pub use self::action::*;
+pub use self::builder::*;
+#[cfg_attr(not(feature = "unstable"), doc(hidden))]
+pub use self::descriptor::*;
+pub use self::deserialize::*;
+#[cfg_attr(not(feature = "unstable"), doc(hidden))]
+pub use self::diff::*;
+#[cfg_attr(not(feature = "unstable"), doc(hidden))]
+pub use self::document::*;
 pub use self::error::*;
 pub use self::model::*;
 pub use self::parser::*;
+pub use self::validate::*;
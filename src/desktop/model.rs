@@ -1,52 +1,391 @@
-use std::collections::HashMap;
+use std::collections::{btree_map, BTreeMap, HashMap};
+use std::env;
+use std::fmt;
+use std::io;
 use std::ops::Index;
 use std::slice;
 
-use super::entries::Entry;
+use indexmap::IndexMap;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-pub const DESKTOP_ENTRY_NAME: &'static str = "Desktop Entry";
+use super::entries::{
+    Comment, DBusActivatable, Entry, Exec, GenericName, Keywords, Name, SerializeEntry, Type, URL,
+};
+use super::error::{EntryError, RequiredKeyError};
 
-#[derive(Debug, PartialEq, Clone)]
+pub const DESKTOP_ENTRY_NAME: &str = "Desktop Entry";
+
+/// The version of the desktop entry specification this crate targets
+pub const SPEC_VERSION: &str = "1.5";
+
+/// A group of key-value pairs
+///
+/// Keys keep the order they were inserted in, which for a parsed group is
+/// the order they appear in the file. With the `serde` feature, this
+/// serializes as its name and raw values.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Group {
     name: String,
-    values: HashMap<String, String>,
+    values: IndexMap<String, String>,
+    /// Original bytes of values that weren't valid UTF-8
+    #[cfg_attr(feature = "serde", serde(skip))]
+    raw_bytes: HashMap<String, Vec<u8>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    ignore_key_case: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    lenient: bool,
+}
+
+/// Groups are equal if they have the same name and values
+///
+/// How values are looked up, set with `set_ignore_key_case` and
+/// `set_lenient`, doesn't matter.
+impl PartialEq for Group {
+    fn eq(&self, other: &Group) -> bool {
+        self.name == other.name && self.values == other.values && self.raw_bytes == other.raw_bytes
+    }
 }
 
 impl Group {
-    pub fn new(name: String, values: HashMap<String, String>) -> Group {
+    pub fn new<I>(name: String, values: I) -> Group
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
         Group {
-            name: name,
-            values: values,
+            name,
+            values: values.into_iter().collect(),
+            raw_bytes: HashMap::new(),
+            ignore_key_case: false,
+            lenient: false,
         }
     }
 
+    /// Make key lookups ignore ASCII case
+    ///
+    /// Keys are case sensitive according to the spec, but some files in the
+    /// wild get the case wrong. An exact match is still preferred.
+    pub fn set_ignore_key_case(&mut self, ignore: bool) {
+        self.ignore_key_case = ignore;
+    }
+
+    /// Accept deprecated values, such as `0` and `1` for booleans
+    ///
+    /// See `Entry::deserialize_lenient`.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 
-    pub fn values(&self) -> &HashMap<String, String> {
+    pub(crate) fn is_lenient(&self) -> bool {
+        self.lenient
+    }
+
+    /// The keys and raw values, in insertion order
+    pub fn values(&self) -> &IndexMap<String, String> {
         &self.values
     }
 
+    /// Iterate over the keys in insertion order
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(String::as_str)
+    }
+
     pub fn get<T: Entry>(&self) -> Option<T> {
-        self.get_raw(T::name()).and_then(T::deserialize)
+        self.get_raw(T::name()).and_then(|v| self.deserialize(v))
     }
 
-    // FIXME: This is overly simplistic, it needs to look up increasingly more general locales
+    /// Like `get`, but an invalid value is an error instead of `None`
+    pub fn get_checked<T: Entry>(&self) -> Result<Option<T>, EntryError> {
+        match self.get_raw(T::name()) {
+            Some(value) => self.deserialize(value).map(Some).ok_or_else(|| EntryError {
+                key: T::name(),
+                value: value.into(),
+            }),
+            None => Ok(None),
+        }
+    }
+
+    /// Check the required keys of a main group, see
+    /// `DesktopEntry::check_required_keys`
+    pub(crate) fn check_required_keys(&self) -> Result<(), RequiredKeyError> {
+        let ty = self
+            .get_checked::<Type>()?
+            .ok_or(RequiredKeyError::MissingKey(Type::name()))?;
+        if let Type::Unknown(value) = ty {
+            let key = Type::name();
+            return Err(EntryError { key, value }.into());
+        }
+        if self.get_raw(Name::name()).is_none() {
+            return Err(RequiredKeyError::MissingKey(Name::name()));
+        }
+        let has_exec = self.get_raw(Exec::name()).is_some();
+        let has_url = self.get_raw(URL::name()).is_some();
+        let dbus_activatable = self.get::<DBusActivatable>().is_some_and(|d| *d);
+        let unexpected = |key| RequiredKeyError::UnexpectedKey {
+            key,
+            ty: SerializeEntry::serialize(&ty),
+        };
+        match ty {
+            Type::Application if has_url => Err(unexpected(URL::name())),
+            Type::Application if !has_exec && !dbus_activatable => {
+                Err(RequiredKeyError::MissingKey(Exec::name()))
+            }
+            Type::Link if has_exec => Err(unexpected(Exec::name())),
+            Type::Link if !has_url => Err(RequiredKeyError::MissingKey(URL::name())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Get a localized entry for a locale such as `sr_RS@latin`
+    ///
+    /// Keys are tried in the order given by the spec: `lang_COUNTRY@MODIFIER`,
+    /// `lang_COUNTRY`, `lang@MODIFIER`, `lang`, and finally the unlocalized key.
+    /// Any `.ENCODING` part of the locale is ignored.
     pub fn get_localized<T: Entry>(&self, locale: &str) -> Option<T> {
-        self.get_raw(&format!("{}[{}]", T::name(), locale))
-            .and_then(T::deserialize)
+        self.get_raw_localized(T::name(), locale)
+            .and_then(|v| self.deserialize(v))
+    }
+
+    /// Get the raw value of a localized key, with the same fallbacks as
+    /// `get_localized`
+    pub fn get_raw_localized(&self, name: &str, locale: &str) -> Option<&str> {
+        locale_fallbacks(locale)
+            .iter()
+            .find_map(|l| self.get_raw(&format!("{}[{}]", name, l)))
+            .or_else(|| self.get_raw(name))
+    }
+
+    /// Get an entry localized for `locale` like `get_localized`, or the
+    /// unlocalized one with `None`
+    pub fn get_for_locale<T: Entry>(&self, locale: Option<&str>) -> Option<T> {
+        match locale {
+            Some(locale) => self.get_localized(locale),
+            None => self.get(),
+        }
+    }
+
+    /// Get a localized entry for the locale of the current process
+    ///
+    /// See `current_locale`.
+    pub fn get_localized_auto<T: Entry>(&self) -> Option<T> {
+        match current_locale() {
+            Some(locale) => self.get_localized(&locale),
+            None => self.get(),
+        }
+    }
+
+    /// Get the unlocalized value and every translation of an entry
+    ///
+    /// Values that can't be deserialized are skipped.
+    pub fn get_all_locales<T: Entry>(&self) -> LocaleMap<T> {
+        let mut map = LocaleMap {
+            default: self.get(),
+            translations: BTreeMap::new(),
+        };
+        for (key, value) in &self.values {
+            let locale = key
+                .strip_prefix(T::name())
+                .and_then(|k| k.strip_prefix('['))
+                .and_then(|k| k.strip_suffix(']'));
+            let Some(locale) = locale else { continue };
+            if let Some(value) = self.deserialize(value) {
+                map.translations.insert(locale.into(), value);
+            }
+        }
+        map
     }
 
     pub fn get_raw(&self, name: &str) -> Option<&str> {
-        // name is case insensitive
-        self.values
-            .get(&name.to_ascii_lowercase())
-            .map(String::as_str)
+        self.find(name).map(|(_, v)| v.as_str())
+    }
+
+    /// Get the value of a key exactly as it appeared in the input
+    ///
+    /// This only differs from `get_raw` for values that contained invalid
+    /// UTF-8 and were parsed with `ParseOptions::replace_invalid`.
+    pub fn get_raw_bytes(&self, name: &str) -> Option<&[u8]> {
+        let (key, value) = self.find(name)?;
+        match self.raw_bytes.get(key) {
+            Some(raw) => Some(raw),
+            None => Some(value.as_bytes()),
+        }
+    }
+
+    /// Get a mutable reference to the raw value of a key
+    pub fn entry_mut(&mut self, name: &str) -> Option<&mut String> {
+        self.raw_bytes.remove(name);
+        self.values.get_mut(name)
+    }
+
+    /// Set the raw (already escaped) value of a key
+    ///
+    /// An existing key keeps its position, a new key is added at the end.
+    /// Returns the previous value, if any.
+    pub fn set_raw(&mut self, name: &str, value: String) -> Option<String> {
+        self.raw_bytes.remove(name);
+        self.values.insert(name.into(), value)
+    }
+
+    /// Set the value of an entry
+    pub fn set<T: SerializeEntry>(&mut self, entry: &T) {
+        self.set_raw(T::name(), entry.serialize());
+    }
+
+    /// Remove a key, returning its raw value if it was present
+    ///
+    /// The order of the remaining keys is kept.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        self.raw_bytes.remove(name);
+        self.values.shift_remove(name)
+    }
+
+    /// Record the original bytes of a value, or forget them if `raw` is `None`
+    pub(crate) fn set_raw_bytes(&mut self, key: &str, raw: Option<Vec<u8>>) {
+        match raw {
+            Some(raw) => self.raw_bytes.insert(key.into(), raw),
+            None => self.raw_bytes.remove(key),
+        };
+    }
+
+    fn deserialize<T: Entry>(&self, value: &str) -> Option<T> {
+        match self.lenient {
+            true => T::deserialize_lenient(value),
+            false => T::deserialize(value),
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<(&String, &String)> {
+        match self.values.get_key_value(name) {
+            Some(kv) => Some(kv),
+            None if self.ignore_key_case => self
+                .values
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name)),
+            None => None,
+        }
+    }
+
+    /// Get the keys and raw values sorted by key
+    ///
+    /// Localized keys directly follow their unlocalized key, ordered by locale,
+    /// so the order doesn't depend on how the group was built.
+    pub fn sorted_values(&self) -> Vec<(&str, &str)> {
+        let mut values: Vec<(&str, &str)> = self
+            .values
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        values.sort_unstable_by_key(|&(k, _)| match k.split_once('[') {
+            Some((base, locale)) => (base, locale),
+            None => (k, ""),
+        });
+        values
+    }
+
+    /// Merge the values of `other` into this group
+    ///
+    /// Keys present in both groups take the value from `other` but keep
+    /// their position, new keys are added at the end.
+    pub fn merge(&mut self, other: Group) {
+        for key in other.values.keys() {
+            self.raw_bytes.remove(key);
+        }
+        self.values.extend(other.values);
+        self.raw_bytes.extend(other.raw_bytes);
     }
 }
 
+/// All translations of a localized entry, from `Group::get_all_locales`
 #[derive(Debug, PartialEq, Clone)]
+pub struct LocaleMap<T> {
+    default: Option<T>,
+    translations: BTreeMap<String, T>,
+}
+
+impl<T> LocaleMap<T> {
+    /// The unlocalized value
+    pub fn default_value(&self) -> Option<&T> {
+        self.default.as_ref()
+    }
+
+    /// The value for exactly this locale
+    pub fn get(&self, locale: &str) -> Option<&T> {
+        self.translations.get(locale)
+    }
+
+    /// The value for a locale, using the same fallbacks as `Group::get_localized`
+    pub fn lookup(&self, locale: &str) -> Option<&T> {
+        locale_fallbacks(locale)
+            .iter()
+            .find_map(|l| self.translations.get(l))
+            .or(self.default.as_ref())
+    }
+
+    /// Iterate over the locales and their values, sorted by locale
+    ///
+    /// This doesn't include the unlocalized value.
+    pub fn iter(&self) -> btree_map::Iter<'_, String, T> {
+        self.translations.iter()
+    }
+
+    /// Iterate over the locales with a translation, in sorted order
+    pub fn locales(&self) -> impl Iterator<Item = &str> {
+        self.translations.keys().map(String::as_str)
+    }
+
+    /// Number of translations, not including the unlocalized value
+    pub fn len(&self) -> usize {
+        self.translations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.translations.is_empty()
+    }
+}
+
+/// The locale used for messages, from `LC_ALL`, `LC_MESSAGES` or `LANG`
+///
+/// Returns `None` if none are set or the locale is `C` or `POSIX`.
+pub fn current_locale() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|l| !l.is_empty())
+        .filter(|l| l != "C" && l != "POSIX")
+}
+
+/// Locales to try, from most to least specific, not including the unlocalized key
+fn locale_fallbacks(locale: &str) -> Vec<String> {
+    let (rest, modifier) = match locale.split_once('@') {
+        Some((rest, modifier)) => (rest, Some(modifier)),
+        None => (locale, None),
+    };
+    let rest = rest.split_once('.').map_or(rest, |(rest, _)| rest);
+    let (lang, country) = match rest.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (rest, None),
+    };
+    let mut locales = Vec::with_capacity(4);
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        locales.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+    if let Some(country) = country {
+        locales.push(format!("{}_{}", lang, country));
+    }
+    if let Some(modifier) = modifier {
+        locales.push(format!("{}@{}", lang, modifier));
+    }
+    locales.push(lang.into());
+    locales
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DesktopEntry(Vec<Group>);
 
 impl DesktopEntry {
@@ -60,29 +399,638 @@ impl DesktopEntry {
     }
 
     /// Get an iterator over all groups in the entry
-    pub fn groups(&self) -> slice::Iter<Group> {
+    pub fn groups(&self) -> slice::Iter<'_, Group> {
         self.0.iter()
     }
 
+    /// Get a mutable reference to a group in the entry by name
+    pub fn group_mut(&mut self, name: &str) -> Option<&mut Group> {
+        self.0.iter_mut().find(|g| g.name == name)
+    }
+
+    /// Add a group, replacing any existing group with the same name
+    ///
+    /// A new group is added at the end. Returns the replaced group, if any.
+    pub fn insert_group(&mut self, group: Group) -> Option<Group> {
+        match self.group_mut(&group.name) {
+            Some(existing) => Some(std::mem::replace(existing, group)),
+            None => {
+                self.0.push(group);
+                None
+            }
+        }
+    }
+
+    /// Remove a group from the entry
+    pub fn remove_group(&mut self, name: &str) -> Option<Group> {
+        let pos = self.0.iter().position(|g| g.name == name)?;
+        Some(self.0.remove(pos))
+    }
+
+    /// Make key lookups in all groups ignore ASCII case
+    ///
+    /// See `Group::set_ignore_key_case`.
+    pub fn set_ignore_key_case(&mut self, ignore: bool) {
+        for group in &mut self.0 {
+            group.set_ignore_key_case(ignore);
+        }
+    }
+
+    /// Accept deprecated values in all groups
+    ///
+    /// See `Group::set_lenient`.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        for group in &mut self.0 {
+            group.set_lenient(lenient);
+        }
+    }
+
     /// Get the "Desktop Entry" group
     pub fn main_group(&self) -> Option<&Group> {
         self.group(DESKTOP_ENTRY_NAME)
     }
 
+    /// Get a mutable reference to the "Desktop Entry" group
+    pub fn main_group_mut(&mut self) -> Option<&mut Group> {
+        self.group_mut(DESKTOP_ENTRY_NAME)
+    }
+
     pub fn action_group(&self, action_name: &str) -> Option<&Group> {
         self.group(&format!("Desktop Action {}", action_name))
     }
 
+    /// Merge another entry into this one
+    ///
+    /// Groups that exist in both entries are merged key by key, with values
+    /// from `other` taking precedence. Groups only present in `other` are
+    /// appended.
+    pub fn merge(&mut self, other: DesktopEntry) {
+        for group in other.0 {
+            match self.0.iter_mut().find(|g| g.name == group.name) {
+                Some(existing) => existing.merge(group),
+                None => self.0.push(group),
+            }
+        }
+    }
+
     /// Shortut for `self.main_group().get()`
     #[inline]
     pub fn get<T: Entry>(&self) -> Option<T> {
         self.main_group().and_then(Group::get)
     }
+
+    /// Shortcut for `self.main_group().get_checked()`
+    pub fn get_checked<T: Entry>(&self) -> Result<Option<T>, EntryError> {
+        self.main_group().map_or(Ok(None), Group::get_checked)
+    }
+
+    /// Check if the entry matches a search query, like application launchers do
+    ///
+    /// Every word of `query` has to appear, ignoring case, in the `Name`,
+    /// `GenericName`, `Comment` or `Keywords` localized for `locale`. With
+    /// `None`, only the unlocalized values are searched.
+    pub fn matches(&self, query: &str, locale: Option<&str>) -> bool {
+        let Some(main) = self.main_group() else {
+            return false;
+        };
+        let mut fields = Vec::new();
+        fields.extend(main.get_for_locale(locale).map(|Name(n)| n));
+        fields.extend(main.get_for_locale(locale).map(|GenericName(n)| n));
+        fields.extend(main.get_for_locale(locale).map(|Comment(c)| c));
+        if let Some(Keywords(keywords)) = main.get_for_locale(locale) {
+            fields.extend(keywords);
+        }
+        let fields: Vec<String> = fields.iter().map(|f| f.to_lowercase()).collect();
+        query
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .all(|word| fields.iter().any(|f| f.contains(&word)))
+    }
+
+    /// Check that the keys required for the entry's `Type` are present
+    ///
+    /// `Type` and `Name` are always required, and `Type` has to be one of
+    /// the types in the spec. Applications need an `Exec` unless they are
+    /// D-Bus activatable, links need a `URL`, and neither may have the
+    /// other's key.
+    pub fn check_required_keys(&self) -> Result<(), RequiredKeyError> {
+        match self.main_group() {
+            Some(main) => main.check_required_keys(),
+            None => Err(RequiredKeyError::MissingKey(Type::name())),
+        }
+    }
+
+    /// Write the entry in desktop file format
+    pub fn write_to<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "{}", self)
+    }
+
+    /// Write the entry with the keys of each group in a fixed order
+    ///
+    /// Unlike `write_to`, the output only depends on the contents of the
+    /// entry, so it can be used for reproducible builds. See `Group::sorted_values`.
+    pub fn write_sorted_to<W: io::Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(self.to_sorted_string().as_bytes())
+    }
+
+    /// Format the entry with the keys of each group in a fixed order
+    ///
+    /// See `write_sorted_to`.
+    pub fn to_sorted_string(&self) -> String {
+        let mut out = String::new();
+        for (i, group) in self.0.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            // writing to a String can't fail
+            let _ = fmt_group(&mut out, &group.name, group.sorted_values());
+        }
+        out
+    }
 }
 
+/// A group borrowing its name, keys and values from the parsed input
+///
+/// See `parse_borrowed`.
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "unstable"), doc(hidden))]
+pub struct GroupRef<'a> {
+    name: &'a str,
+    values: Vec<(&'a str, ValueRef<'a>)>,
+    ignore_key_case: bool,
+    lenient: bool,
+}
+
+/// A value of a `GroupRef`
+///
+/// Values are borrowed from the input, unless they weren't valid UTF-8 and
+/// were parsed with `ParseOptions::replace_invalid`.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum ValueRef<'a> {
+    Str(&'a str),
+    Invalid { replaced: String, raw: &'a [u8] },
+}
+
+impl<'a> ValueRef<'a> {
+    fn as_str(&self) -> &str {
+        match *self {
+            ValueRef::Str(s) => s,
+            ValueRef::Invalid { ref replaced, .. } => replaced,
+        }
+    }
+
+    fn raw_bytes(&self) -> &'a [u8] {
+        match *self {
+            ValueRef::Str(s) => s.as_bytes(),
+            ValueRef::Invalid { raw, .. } => raw,
+        }
+    }
+}
+
+/// Like for `Group`, only the name and values are compared
+impl PartialEq for GroupRef<'_> {
+    fn eq(&self, other: &GroupRef<'_>) -> bool {
+        self.name == other.name && self.values == other.values
+    }
+}
+
+impl<'a> GroupRef<'a> {
+    pub fn new(name: &'a str, values: Vec<(&'a str, &'a str)>) -> GroupRef<'a> {
+        let values = values
+            .into_iter()
+            .map(|(k, v)| (k, ValueRef::Str(v)))
+            .collect();
+        GroupRef::with_values(name, values)
+    }
+
+    pub(crate) fn with_values(name: &'a str, values: Vec<(&'a str, ValueRef<'a>)>) -> GroupRef<'a> {
+        GroupRef {
+            name,
+            values,
+            ignore_key_case: false,
+            lenient: false,
+        }
+    }
+
+    /// Make key lookups ignore ASCII case
+    ///
+    /// See `Group::set_ignore_key_case`.
+    pub fn set_ignore_key_case(&mut self, ignore: bool) {
+        self.ignore_key_case = ignore;
+    }
+
+    /// Accept deprecated values
+    ///
+    /// See `Group::set_lenient`.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Iterate over the keys and raw values in file order
+    pub fn values(&self) -> impl Iterator<Item = (&'a str, &str)> + '_ {
+        self.values.iter().map(|(k, v)| (*k, v.as_str()))
+    }
+
+    pub fn get<T: Entry>(&self) -> Option<T> {
+        let value = self.get_raw(T::name())?;
+        match self.lenient {
+            true => T::deserialize_lenient(value),
+            false => T::deserialize(value),
+        }
+    }
+
+    pub fn get_raw(&self, name: &str) -> Option<&str> {
+        self.find(name).map(ValueRef::as_str)
+    }
+
+    /// Get the value of a key exactly as it appeared in the input
+    ///
+    /// See `Group::get_raw_bytes`.
+    pub fn get_raw_bytes(&self, name: &str) -> Option<&'a [u8]> {
+        self.find(name).map(ValueRef::raw_bytes)
+    }
+
+    fn find(&self, name: &str) -> Option<&ValueRef<'a>> {
+        // the last value wins like in `Group`
+        let mut values = self.values.iter().rev();
+        values
+            .clone()
+            .find(|(k, _)| *k == name)
+            .or_else(|| match self.ignore_key_case {
+                true => values.find(|(k, _)| k.eq_ignore_ascii_case(name)),
+                false => None,
+            })
+            .map(|(_, v)| v)
+    }
+
+    /// Add the values of another group with the same name
+    pub(crate) fn merge(&mut self, other: GroupRef<'a>) {
+        self.values.extend(other.values);
+    }
+
+    /// Copy into an owned `Group`
+    pub fn to_owned(&self) -> Group {
+        let values = self
+            .values
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.as_str().to_string()));
+        let mut group = Group::new(self.name.into(), values);
+        group.set_ignore_key_case(self.ignore_key_case);
+        group.set_lenient(self.lenient);
+        // in order, so a later valid value forgets the bytes of an invalid one
+        for (key, value) in &self.values {
+            let raw = match *value {
+                ValueRef::Invalid { raw, .. } => Some(raw.to_vec()),
+                ValueRef::Str(_) => None,
+            };
+            group.set_raw_bytes(key, raw);
+        }
+        group
+    }
+}
+
+/// A desktop entry borrowing from the parsed input
+///
+/// See `parse_borrowed`.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(not(feature = "unstable"), doc(hidden))]
+pub struct DesktopEntryRef<'a>(Vec<GroupRef<'a>>);
+
+impl<'a> DesktopEntryRef<'a> {
+    pub fn new(groups: Vec<GroupRef<'a>>) -> DesktopEntryRef<'a> {
+        DesktopEntryRef(groups)
+    }
+
+    /// Get a group in the entry by name
+    pub fn group(&self, name: &str) -> Option<&GroupRef<'a>> {
+        self.0.iter().find(|g| g.name == name)
+    }
+
+    /// Get an iterator over all groups in the entry
+    pub fn groups(&self) -> slice::Iter<'_, GroupRef<'a>> {
+        self.0.iter()
+    }
+
+    /// Get the "Desktop Entry" group
+    pub fn main_group(&self) -> Option<&GroupRef<'a>> {
+        self.group(DESKTOP_ENTRY_NAME)
+    }
+
+    /// Shortut for `self.main_group().get()`
+    #[inline]
+    pub fn get<T: Entry>(&self) -> Option<T> {
+        self.main_group().and_then(GroupRef::get)
+    }
+
+    /// Copy into an owned `DesktopEntry`
+    pub fn to_owned(&self) -> DesktopEntry {
+        DesktopEntry::new(self.0.iter().map(GroupRef::to_owned).collect())
+    }
+}
+
+fn fmt_group<'a, W, I>(out: &mut W, name: &str, values: I) -> fmt::Result
+where
+    W: fmt::Write,
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    writeln!(out, "[{}]", name)?;
+    for (key, value) in values {
+        // Values are stored escaped, but a raw line break would
+        // end the value early, so escape those as well
+        writeln!(
+            out,
+            "{}={}",
+            key,
+            value.replace('\n', "\\n").replace('\r', "\\r")
+        )?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Group {
+    /// Format the group in desktop file format, including the header
+    ///
+    /// Keys are written in insertion order, use `sorted_values` for an order
+    /// that only depends on the contents.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let values = self.values.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+        fmt_group(f, &self.name, values)
+    }
+}
+
+impl fmt::Display for DesktopEntry {
+    /// Format the entry in desktop file format
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, group) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", group)?;
+        }
+        Ok(())
+    }
+}
+
+/// Look up a group by name
+///
+/// # Panics
+///
+/// Panics if there is no group with that name. Use `DesktopEntry::group`
+/// when the group may be missing, e.g. for any input from the user.
 impl<'a> Index<&'a str> for DesktopEntry {
     type Output = Group;
     fn index(&self, group_name: &'a str) -> &Group {
         self.group(group_name).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn edit_test() {
+        use crate::entries::{Exec, Keywords, Terminal};
+
+        let mut entry = parse("[Desktop Entry]\nName=Foo\nExec=foo\nIcon=foo\n").unwrap();
+        let group = entry.main_group_mut().unwrap();
+        group.set(&Exec("foo --new %U".into()));
+        group.set(&Terminal(true));
+        group.set(&Keywords(vec!["a".into(), "b;c".into()]));
+        group.entry_mut("Name").unwrap().push_str("\\sBar");
+        assert_eq!(group.remove("Icon"), Some("foo".into()));
+        assert_eq!(entry.remove_group("Missing"), None);
+        let action = Group::new("Desktop Action New".into(), Vec::new());
+        assert_eq!(entry.insert_group(action.clone()), None);
+        assert_eq!(entry.insert_group(action.clone()), Some(action));
+
+        let expected = "\
+[Desktop Entry]
+Exec=foo --new %U
+Keywords=a;b\\;c;
+Name=Foo\\sBar
+Terminal=true
+
+[Desktop Action New]
+";
+        assert_eq!(entry.to_sorted_string(), expected);
+        let reparsed = parse(expected).unwrap();
+        assert_eq!(reparsed.get::<Exec>(), Some(Exec("foo --new %U".into())));
+        assert_eq!(
+            reparsed.get::<Keywords>(),
+            Some(Keywords(vec!["a".into(), "b;c".into()]))
+        );
+    }
+
+    #[test]
+    fn key_order_test() {
+        let input = "\
+[Desktop Entry]
+Type=Application
+Name=Foo
+Exec=foo %U
+Icon=foo
+
+[Desktop Action new]
+Name=New
+Exec=foo --new
+";
+        let mut entry = parse(input).unwrap();
+        assert_eq!(entry.to_string(), input);
+        let group = entry.main_group_mut().unwrap();
+        assert_eq!(
+            group.keys().collect::<Vec<_>>(),
+            vec!["Type", "Name", "Exec", "Icon"]
+        );
+        group.remove("Name");
+        group.set_raw("Exec", "foo".into());
+        group.set_raw("Terminal", "false".into());
+        assert_eq!(
+            group.keys().collect::<Vec<_>>(),
+            vec!["Type", "Exec", "Icon", "Terminal"]
+        );
+        let (entry, _) = crate::parse_lenient(input);
+        assert_eq!(entry.to_string(), input);
+    }
+
+    #[test]
+    fn get_localized_test() {
+        use crate::entries::Name;
+
+        let entry = parse(
+            "\
+[Desktop Entry]
+Name=Default
+Name[sr]=Serbian
+Name[sr_RS]=Serbia
+Name[sr@latin]=Latin
+Name[de_DE@euro]=Euro
+",
+        )
+        .unwrap();
+        let group = entry.main_group().unwrap();
+        let name = |locale| group.get_localized::<Name>(locale).unwrap().0;
+        assert_eq!(name("sr_RS@latin"), "Serbia");
+        assert_eq!(name("sr_ME@latin"), "Latin");
+        assert_eq!(name("sr_ME.UTF-8"), "Serbian");
+        assert_eq!(name("sr"), "Serbian");
+        assert_eq!(name("de_DE.UTF-8@euro"), "Euro");
+        assert_eq!(name("de_DE"), "Default");
+        assert_eq!(name("fr"), "Default");
+    }
+
+    #[test]
+    fn locale_map_test() {
+        use crate::entries::Name;
+
+        let entry = parse(
+            "\
+[Desktop Entry]
+Name=Default
+Name[sr]=Serbian
+Name[sr_RS]=Serbia
+Name[sr@latin]=Latin
+Name[de_DE@euro]=Euro
+",
+        )
+        .unwrap();
+        let group = entry.main_group().unwrap();
+        let names = group.get_all_locales::<Name>();
+        assert_eq!(names.default_value(), Some(&Name("Default".into())));
+        assert_eq!(
+            names.locales().collect::<Vec<_>>(),
+            ["de_DE@euro", "sr", "sr@latin", "sr_RS"]
+        );
+        assert_eq!(names.get("sr_RS"), Some(&Name("Serbia".into())));
+        assert_eq!(names.get("sr_RS@latin"), None);
+        assert_eq!(names.lookup("sr_RS@latin"), Some(&Name("Serbia".into())));
+        assert_eq!(names.lookup("fr"), Some(&Name("Default".into())));
+    }
+
+    #[test]
+    fn get_checked_test() {
+        use crate::entries::{Hidden, NoDisplay, Terminal};
+
+        let entry = parse("[Desktop Entry]\nHidden=yes\nTerminal=true\n").unwrap();
+        assert_eq!(entry.get::<Hidden>(), None);
+        assert_eq!(
+            entry.get_checked::<Hidden>(),
+            Err(EntryError {
+                key: "Hidden",
+                value: "yes".into()
+            })
+        );
+        assert_eq!(entry.get_checked::<Terminal>(), Ok(Some(Terminal(true))));
+        assert_eq!(entry.get_checked::<NoDisplay>(), Ok(None));
+    }
+
+    #[test]
+    fn lenient_values_test() {
+        use crate::entries::{GnomeAutostartDelay, Hidden, Terminal};
+
+        let input = "[Desktop Entry]\nHidden=1\nTerminal=0\nX-GNOME-Autostart-Delay=2.5\n";
+        let entry = parse(input).unwrap();
+        assert_eq!(entry.get::<Hidden>(), None);
+        assert_eq!(
+            entry.get::<GnomeAutostartDelay>(),
+            Some(GnomeAutostartDelay(2.5))
+        );
+
+        let options = crate::ParseOptions::new().lenient(true);
+        let entry = options.parse(input).unwrap();
+        assert_eq!(entry.get::<Hidden>(), Some(Hidden(true)));
+        assert_eq!(entry.get::<Terminal>(), Some(Terminal(false)));
+        let entry = options.parse_borrowed(input).unwrap();
+        assert_eq!(entry.get::<Hidden>(), Some(Hidden(true)));
+
+        let entry = parse("[Desktop Entry]\nX-GNOME-Autostart-Delay=soon\n").unwrap();
+        assert!(entry.get_checked::<GnomeAutostartDelay>().is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_test() {
+        use crate::entries::Name;
+
+        let entry = parse("[Desktop Entry]\nType=Application\nName=Foo\n").unwrap();
+        let json = serde_json::to_string(&entry).unwrap();
+        assert_eq!(serde_json::from_str::<DesktopEntry>(&json).unwrap(), entry);
+
+        let json = r#"[{"name": "Desktop Entry", "values": {"Name": "Bar"}}]"#;
+        let entry: DesktopEntry = serde_json::from_str(json).unwrap();
+        assert_eq!(entry.get::<Name>(), Some(Name("Bar".into())));
+    }
+
+    #[test]
+    fn group_eq_test() {
+        let input = "[Desktop Entry]\nName=Foo\n";
+        let entry = parse(input).unwrap();
+        let mut group = entry.main_group().unwrap().clone();
+        group.set_ignore_key_case(true);
+        group.set_lenient(true);
+        assert_eq!(&group, entry.main_group().unwrap());
+        group.set_raw("Name", "Bar".into());
+        assert_ne!(&group, entry.main_group().unwrap());
+
+        let borrowed = crate::parse_borrowed(input).unwrap();
+        let mut group = borrowed.main_group().unwrap().clone();
+        group.set_ignore_key_case(true);
+        assert_eq!(&group, borrowed.main_group().unwrap());
+    }
+
+    #[test]
+    fn check_required_keys_test() {
+        let entry = parse("[Desktop Entry]\nType=Link\nName=Example\n").unwrap();
+        assert_eq!(
+            entry.check_required_keys(),
+            Err(RequiredKeyError::MissingKey("URL"))
+        );
+        let entry =
+            parse("[Desktop Entry]\nType=Link\nName=Example\nURL=https://example.com\n").unwrap();
+        assert_eq!(entry.check_required_keys(), Ok(()));
+        let entry = parse("[Desktop Entry]\nType=Unknown\nName=Example\n").unwrap();
+        assert_eq!(
+            entry.check_required_keys(),
+            Err(RequiredKeyError::InvalidValue(EntryError {
+                key: "Type",
+                value: "Unknown".into()
+            }))
+        );
+        let entry = parse("[X-Other]\n").unwrap();
+        assert_eq!(
+            entry.check_required_keys(),
+            Err(RequiredKeyError::MissingKey("Type"))
+        );
+    }
+
+    #[test]
+    fn matches_test() {
+        let entry = parse(
+            "\
+[Desktop Entry]
+Name=Text Editor
+Name[de]=Texteditor
+GenericName=Editor
+Comment=Edit text files
+Keywords=Plaintext;Write;
+Keywords[de]=Notizen;
+",
+        )
+        .unwrap();
+        assert!(entry.matches("text", None));
+        assert!(entry.matches("EDIT write", None));
+        assert!(entry.matches("", None));
+        assert!(!entry.matches("edit notizen", None));
+        assert!(entry.matches("texted notizen", Some("de_DE.UTF-8")));
+        // translations replace the unlocalized values
+        assert!(!entry.matches("write", Some("de_DE")));
+        assert!(!parse("[X-Other]\nName=Text\n")
+            .unwrap()
+            .matches("text", None));
+    }
+}
@@ -1,21 +1,22 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io;
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Read};
 use std::path::Path;
 use std::str;
 
+use indexmap::IndexMap;
+use memchr::{memchr, memchr2};
 use nom::{
-    bytes::complete::take_while,
-    character::complete::{char, space0},
-    combinator::{all_consuming, eof, map, map_res, value},
-    multi::{fold_many0, many0},
-    sequence::{delimited, preceded, separated_pair, terminated},
-    Finish, InputTakeAtPosition, Parser,
+    branch::alt,
+    bytes::complete::{tag, take, take_till1, take_while1},
+    character::complete::{alpha1, alphanumeric1, char, space0},
+    combinator::{all_consuming, cut, eof, map, map_res, opt, peek, recognize, rest},
+    error::{ErrorKind, ParseError as _},
+    sequence::{delimited, pair, preceded, terminated, tuple},
+    Finish,
 };
-use nom_regex::bytes::re_find;
-use once_cell::sync::OnceCell;
-use regex::bytes::Regex;
 
+use super::document::*;
 use super::error::*;
 use super::model::*;
 
@@ -23,105 +24,822 @@ pub type ParseResult = Result<DesktopEntry, ParseError>;
 
 type IResult<'a, T> = nom::IResult<&'a [u8], T, ParseError>;
 
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Options controlling how desktop files are parsed
+///
+/// The default options accept everything `parse` does. Use the builder
+/// methods to change them, e.g. `ParseOptions::new().strict(true).parse(input)`.
+///
+/// When parsing untrusted input, set the size and count limits so a
+/// malicious file can't make the parser use unbounded memory.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ParseOptions {
+    strict: bool,
+    max_size: Option<usize>,
+    max_groups: Option<usize>,
+    max_keys: Option<usize>,
+    crlf: bool,
+    bom: bool,
+    replace_invalid: bool,
+    ignore_key_case: bool,
+    lenient: bool,
+    compat: Compat,
+    mime_keys: bool,
+}
+
+/// Another implementation whose parser quirks to emulate, see `ParseOptions::compat`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Compat {
+    /// Follow the spec
+    #[default]
+    Spec,
+    /// Accept the same files as GLib's `GKeyFile`, which GNOME uses
+    ///
+    /// Lines may be indented, keys may contain any character other than `=`,
+    /// and a group that appears more than once is merged into the first one.
+    Glib,
+}
+
+impl ParseOptions {
+    pub fn new() -> ParseOptions {
+        ParseOptions::default()
+    }
+
+    /// Reject input that the spec forbids but is otherwise unambiguous.
+    ///
+    /// Currently this makes duplicate keys in a group and duplicate groups
+    /// errors, rather than keeping the last value and separate groups.
+    pub fn strict(mut self, strict: bool) -> ParseOptions {
+        self.strict = strict;
+        self
+    }
+
+    /// Limit the size of the input in bytes.
+    pub fn max_size(mut self, max: usize) -> ParseOptions {
+        self.max_size = Some(max);
+        self
+    }
+
+    /// Limit the number of groups in the input.
+    pub fn max_groups(mut self, max: usize) -> ParseOptions {
+        self.max_groups = Some(max);
+        self
+    }
+
+    /// Limit the number of keys in any single group.
+    pub fn max_keys(mut self, max: usize) -> ParseOptions {
+        self.max_keys = Some(max);
+        self
+    }
+
+    /// Accept `\r\n` line endings as well as `\n`.
+    ///
+    /// Otherwise a carriage return is treated as part of the line.
+    pub fn crlf(mut self, accept: bool) -> ParseOptions {
+        self.crlf = accept;
+        self
+    }
+
+    /// Skip a UTF-8 byte order mark at the start of the input.
+    pub fn bom(mut self, accept: bool) -> ParseOptions {
+        self.bom = accept;
+        self
+    }
+
+    /// Replace invalid UTF-8 in values with U+FFFD instead of failing.
+    ///
+    /// The original bytes are kept, see `Group::get_raw_bytes`.
+    pub fn replace_invalid(mut self, replace: bool) -> ParseOptions {
+        self.replace_invalid = replace;
+        self
+    }
+
+    /// Make key lookups on the parsed groups ignore ASCII case.
+    ///
+    /// Keys always keep their original case. See `Group::set_ignore_key_case`.
+    pub fn ignore_key_case(mut self, ignore: bool) -> ParseOptions {
+        self.ignore_key_case = ignore;
+        self
+    }
+
+    /// Accept deprecated values, such as `0` and `1` for booleans, when
+    /// getting entries from the parsed groups.
+    ///
+    /// See `Group::set_lenient`.
+    pub fn lenient(mut self, lenient: bool) -> ParseOptions {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Emulate the parser of another implementation.
+    ///
+    /// This applies to `parse`, `parse_borrowed` and the functions built on
+    /// them, but not to `parse_stream`. Duplicate groups are still an error
+    /// in strict mode.
+    pub fn compat(mut self, compat: Compat) -> ParseOptions {
+        self.compat = compat;
+        self
+    }
+
+    /// Accept mime types such as `image/svg+xml` as keys, for `mimeapps.list`
+    /// and similar files.
+    pub(crate) fn mime_keys(mut self, allow: bool) -> ParseOptions {
+        self.mime_keys = allow;
+        self
+    }
+
+    /// Parse a slice of bytes into a `DesktopEntry` using these options.
+    ///
+    /// With the `encoding` feature, files that aren't valid UTF-8 but declare
+    /// `Encoding=Legacy-Mixed` are transcoded from Latin-1 first.
+    pub fn parse<T: AsRef<[u8]>>(&self, input: T) -> ParseResult {
+        let input = input.as_ref();
+        #[cfg(feature = "encoding")]
+        if let Some(decoded) = self.decode_legacy(input) {
+            // the size limit applies to the input, which was already checked
+            let options = ParseOptions {
+                max_size: None,
+                ..*self
+            };
+            return options
+                .parse_bytes(decoded.as_bytes())
+                .map(|e| e.to_owned());
+        }
+        self.parse_bytes(input).map(|e| e.to_owned())
+    }
+
+    /// Parse into a `DesktopEntryRef` borrowing from the input, using these options.
+    ///
+    /// With the `encoding` feature, files declaring `Encoding=Legacy-Mixed`
+    /// that aren't valid UTF-8 are a `ParseError::LegacyEncoding`, since they
+    /// have to be transcoded. Use `parse` for those.
+    #[cfg_attr(not(feature = "unstable"), doc(hidden))]
+    pub fn parse_borrowed<'a, T: AsRef<[u8]> + ?Sized>(
+        &self,
+        input: &'a T,
+    ) -> Result<DesktopEntryRef<'a>, ParseError> {
+        let input = input.as_ref();
+        #[cfg(feature = "encoding")]
+        if self.decode_legacy(input).is_some() {
+            return Err(ParseError::LegacyEncoding);
+        }
+        self.parse_bytes(input)
+    }
+
+    fn parse_bytes<'a>(&self, mut input: &'a [u8]) -> Result<DesktopEntryRef<'a>, ParseError> {
+        self.check_size(input)?;
+        if self.bom {
+            input = input.strip_prefix(UTF8_BOM).unwrap_or(input);
+        }
+        all_consuming(|i| desktop_entry(*self, i))(input)
+            .finish()
+            .map(|(_, e)| e)
+            .map_err(|e| e.locate(input))
+    }
+
+    /// Read and parse a `DesktopEntry` using these options.
+    pub fn parse_io<T: io::Read>(&self, input: &mut T) -> ParseResult {
+        let mut buf = Vec::new();
+        match self.max_size {
+            // read one byte past the limit, so we know if it was exceeded
+            Some(max) => (&mut *input).take(max as u64 + 1).read_to_end(&mut buf)?,
+            None => input.read_to_end(&mut buf)?,
+        };
+        diagnostic!(trace, bytes = buf.len(), "read desktop entry");
+        self.parse(buf)
+    }
+
+    fn check_size(&self, input: &[u8]) -> Result<(), ParseError> {
+        match self.max_size {
+            Some(max) if input.len() > max => {
+                // report the line containing the first byte over the limit
+                let err = ParseError::limit(Limit::Size(max), &input[max..]);
+                Err(err.locate(input))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn new_group(&self, name: String, values: IndexMap<String, String>) -> Group {
+        let mut group = Group::new(name, values);
+        group.set_ignore_key_case(self.ignore_key_case);
+        group.set_lenient(self.lenient);
+        group
+    }
+
+    /// Transcode the input if it isn't UTF-8 and has a legacy `Encoding` key
+    ///
+    /// Old KDE files could have values in any encoding, but Latin-1 (read as
+    /// its superset Windows-1252) is by far the most common. The key is
+    /// found with these options, replacing invalid UTF-8, so it is read the
+    /// same way as any other key.
+    #[cfg(feature = "encoding")]
+    fn decode_legacy(&self, input: &[u8]) -> Option<String> {
+        if str::from_utf8(input).is_ok() {
+            return None;
+        }
+        let lossy = ParseOptions {
+            replace_invalid: true,
+            ..*self
+        };
+        // if the input can't be parsed, parsing it without decoding reports the error
+        let legacy = lossy.parse_bytes(input).is_ok_and(|entry| {
+            let encoding = entry.main_group().and_then(|g| g.get_raw("Encoding"));
+            encoding == Some("Legacy-Mixed")
+        });
+        legacy.then(|| decode_latin1(input))
+    }
+
+    /// Parse a `DesktopEntry` one line at a time using these options.
+    ///
+    /// See `parse_stream`. With the `encoding` feature, lines after
+    /// `Encoding=Legacy-Mixed` that aren't valid UTF-8 are transcoded from
+    /// Latin-1. Since the input isn't read ahead, earlier lines aren't.
+    pub fn parse_stream<R: io::BufRead>(&self, mut input: R) -> ParseResult {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut current: Option<(String, IndexMap<String, String>)> = None;
+        let mut raw_values = Vec::new();
+        #[cfg(feature = "encoding")]
+        let mut legacy = false;
+        let mut buf = Vec::new();
+        let mut line = 0;
+        let mut size = 0;
+        loop {
+            buf.clear();
+            let read = match self.max_size {
+                Some(max) => {
+                    let remaining = (max - size) as u64 + 1;
+                    (&mut input).take(remaining).read_until(b'\n', &mut buf)?
+                }
+                None => input.read_until(b'\n', &mut buf)?,
+            };
+            if read == 0 {
+                break;
+            }
+            size += read;
+            line += 1;
+            let mut raw = buf.strip_suffix(b"\n").unwrap_or(&buf);
+            if let Some(max) = self.max_size.filter(|&max| size > max) {
+                return Err(ParseError::limit(Limit::Size(max), raw).on_line(line));
+            }
+            if self.crlf {
+                raw = raw.strip_suffix(b"\r").unwrap_or(raw);
+            }
+            if self.bom && line == 1 {
+                raw = raw.strip_prefix(UTF8_BOM).unwrap_or(raw);
+            }
+            #[cfg(feature = "encoding")]
+            let decoded;
+            #[cfg(feature = "encoding")]
+            if legacy && str::from_utf8(raw).is_err() {
+                decoded = decode_latin1(raw);
+                raw = decoded.as_bytes();
+            }
+            let lossy;
+            let mut invalid = None;
+            if self.replace_invalid {
+                lossy = String::from_utf8_lossy(raw);
+                if let Cow::Owned(_) = lossy {
+                    invalid = raw_entry(raw).ok().map(|(_, (_, _, value))| value.to_vec());
+                }
+                raw = lossy.as_bytes();
+            }
+            let located = |e: ParseError, group: Option<&str>| match group {
+                Some(g) => e.in_group(g).on_line(line),
+                None => e.on_line(line),
+            };
+            let parsed = document_line(raw).finish();
+            let (_, parsed) = parsed.map_err(|e| located(e, current.as_ref().map(|c| &*c.0)))?;
+            match (parsed, &mut current) {
+                (DocumentLine::Header(name), _) => {
+                    let duplicate = groups
+                        .iter()
+                        .map(Group::name)
+                        .chain(current.as_ref().map(|c| &*c.0))
+                        .any(|g| g == name);
+                    if self.strict && duplicate {
+                        return Err(located(ParseError::duplicate_group(raw), Some(&name)));
+                    }
+                    let count = groups.len() + current.is_some() as usize;
+                    if let Some(max) = self.max_groups.filter(|&max| count >= max) {
+                        let err = ParseError::limit(Limit::Groups(max), raw);
+                        return Err(located(err, Some(&name)));
+                    }
+                    if let Some((name, values)) = current.take() {
+                        groups.push(self.new_group(name, values));
+                    }
+                    current = Some((name, IndexMap::new()));
+                }
+                (DocumentLine::Line(Line::Entry(kv)), Some((ref group, ref mut values))) => {
+                    let key = kv.key().to_owned();
+                    if let Some(max) = self.max_keys.filter(|&max| values.len() >= max) {
+                        let err = ParseError::limit(Limit::Keys(max), raw);
+                        return Err(located(err.in_key(kv.key()), Some(group)));
+                    }
+                    if self.replace_invalid {
+                        raw_values.push((group.clone(), key.clone(), invalid));
+                    }
+                    #[cfg(feature = "encoding")]
+                    if group == "Desktop Entry" && key == "Encoding" {
+                        legacy = kv.value() == "Legacy-Mixed";
+                    }
+                    if values.insert(key, kv.value().into()).is_some() && self.strict {
+                        let err = ParseError::duplicate_key(raw).in_key(kv.key());
+                        return Err(located(err, Some(group)));
+                    }
+                }
+                (DocumentLine::Line(Line::Entry(_)), None) => {
+                    let err = ParseError::from_error_kind(raw, ErrorKind::Tag);
+                    return Err(located(err, None));
+                }
+                (DocumentLine::Line(_), _) => {}
+            }
+        }
+        if let Some((name, values)) = current {
+            groups.push(self.new_group(name, values));
+        }
+        let mut entry = DesktopEntry::new(groups);
+        if raw_values.iter().any(|(_, _, raw)| raw.is_some()) {
+            restore_raw_bytes(&mut entry, raw_values);
+        }
+        Ok(entry)
+    }
+
+    /// Parse a file into a `DesktopEntry` using these options.
+    pub fn parse_file<T: AsRef<Path>>(&self, path: T) -> ParseResult {
+        diagnostic!(debug, path = ?path.as_ref(), "parsing desktop file");
+        self.parse_io(&mut File::open(path)?)
+    }
+}
+
 /// Parse a slice of bytes into a `DesktopEntry`.
 ///
 /// This parses a .desktop file (or similar) into a `DesktopEntry`.
 /// If it is unable to successfully parse it returns an `Err`
 pub fn parse<T: AsRef<[u8]>>(input: T) -> ParseResult {
-    all_consuming(desktop_entry)(input.as_ref())
-        .finish()
-        .map(|(_, e)| e)
+    ParseOptions::default().parse(input)
+}
+
+/// Parse without copying, so group names, keys and values borrow from the input.
+///
+/// This avoids allocating for every key and value, which adds up when
+/// indexing many files. Use `DesktopEntryRef::to_owned` to get a
+/// `DesktopEntry` when needed.
+#[cfg_attr(not(feature = "unstable"), doc(hidden))]
+pub fn parse_borrowed<T: AsRef<[u8]> + ?Sized>(
+    input: &T,
+) -> Result<DesktopEntryRef<'_>, ParseError> {
+    ParseOptions::default().parse_borrowed(input)
+}
+
+/// Parse a `DesktopEntry` from a reader one line at a time.
+///
+/// Unlike `parse_io`, this never holds the whole input in memory, so it is
+/// suitable for very large or slow inputs such as pipes.
+pub fn parse_stream<R: io::BufRead>(input: R) -> ParseResult {
+    ParseOptions::default().parse_stream(input)
 }
 
 pub fn parse_io<T: io::Read>(input: &mut T) -> ParseResult {
-    let mut buf = Vec::new();
-    input.read_to_end(&mut buf)?;
-    println!("Read: {}", str::from_utf8(&buf).unwrap());
-    parse(buf)
+    ParseOptions::default().parse_io(input)
 }
 
 pub fn parse_file<T: AsRef<Path>>(path: T) -> ParseResult {
-    println!("Path: {:?}", path.as_ref());
-    parse_io(&mut File::open(path)?)
+    ParseOptions::default().parse_file(path)
 }
 
-fn desktop_entry(input: &[u8]) -> IResult<DesktopEntry> {
-    preceded(blanks, map(many0(group), DesktopEntry::new))(input)
+/// Parse a file along with any drop-in overrides for it.
+///
+/// This is an extension to the desktop entry spec. In addition to `foo.desktop`,
+/// any files in `foo.desktop.d/` that end in `.conf` are parsed and merged,
+/// key by key, into the result in lexical order of their file names. This
+/// allows overriding individual keys without copying the whole file.
+pub fn parse_file_with_dropins<T: AsRef<Path>>(path: T) -> ParseResult {
+    let path = path.as_ref();
+    let mut entry = parse_file(path)?;
+
+    let mut dropin_dir = path.as_os_str().to_owned();
+    dropin_dir.push(".d");
+    let dir = match fs::read_dir(&dropin_dir) {
+        Ok(dir) => dir,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(entry),
+        Err(e) => return Err(e.into()),
+    };
+    let mut fragments = Vec::new();
+    for file in dir {
+        let file = file?.path();
+        if file.extension() == Some("conf".as_ref()) && file.is_file() {
+            fragments.push(file);
+        }
+    }
+    fragments.sort();
+    for fragment in fragments {
+        entry.merge(parse_file(fragment)?);
+    }
+    Ok(entry)
 }
 
-fn group(i: &[u8]) -> IResult<Group> {
-    let header = delimited(char('['), take_while(is_header_char), char(']'));
+/// Parse a slice of bytes into a `DesktopDocument`.
+///
+/// Unlike `parse`, this keeps comments, blank lines and the original
+/// formatting of each line, so the document can be written back unchanged.
+/// Input transcoded because of `Encoding=Legacy-Mixed` is written back as
+/// UTF-8 though.
+#[cfg_attr(not(feature = "unstable"), doc(hidden))]
+pub fn parse_document<T: AsRef<[u8]>>(input: T) -> Result<DesktopDocument, ParseError> {
+    let input = input.as_ref();
+    #[cfg(feature = "encoding")]
+    if let Some(decoded) = ParseOptions::default().decode_legacy(input) {
+        return parse_document(decoded);
+    }
+    let (body, final_newline) = match input.strip_suffix(b"\n") {
+        Some(body) => (body, true),
+        None => (input, false),
+    };
+    let mut preamble = Vec::new();
+    let mut groups: Vec<DocumentGroup> = Vec::new();
+    if input.is_empty() {
+        return Ok(DesktopDocument::from_parts(preamble, groups, false));
+    }
+    for (n, raw) in body.split(|&c| c == b'\n').enumerate() {
+        let in_group = |e: ParseError| match groups.last() {
+            Some(g) => e.in_group(g.name()),
+            None => e,
+        };
+        let line = match document_line(raw).finish() {
+            Ok((_, line)) => line,
+            Err(e) => return Err(in_group(e).on_line(n + 1)),
+        };
+        match line {
+            DocumentLine::Header(name) => groups.push(DocumentGroup::new(name)),
+            DocumentLine::Line(line) => match groups.last_mut() {
+                Some(group) => group.push_line(line),
+                None if matches!(line, Line::Entry(_)) => {
+                    let err = ParseError::from_error_kind(raw, ErrorKind::Tag);
+                    return Err(err.on_line(n + 1));
+                }
+                None => preamble.push(line),
+            },
+        }
+    }
+    Ok(DesktopDocument::from_parts(preamble, groups, final_newline))
+}
 
-    let (i, name) = map_res(header, str::from_utf8)(i)?;
-    let (i, values) = delimited(char('\n'), key_value_list, blanks)(i)?;
-    Ok((i, Group::new(name.into(), values)))
+/// Parse a slice of bytes into a `DesktopEntry`, skipping invalid lines.
+///
+/// Real-world files often contain junk lines, duplicate keys or invalid
+/// UTF-8. Rather than failing, this skips any line that can't be parsed and
+/// reports it as a `Warning` alongside the entry. Duplicate keys keep the
+/// last value and duplicate groups are merged, also with a warning.
+pub fn parse_lenient<T: AsRef<[u8]>>(input: T) -> (DesktopEntry, Vec<Warning>) {
+    let input = input.as_ref();
+    let body = input.strip_suffix(b"\n").unwrap_or(input);
+    let mut groups: Vec<(String, IndexMap<String, String>)> = Vec::new();
+    let mut current = None;
+    let mut warnings = Vec::new();
+    let lines = body.split(|&c| c == b'\n').filter(|_| !input.is_empty());
+    for (n, raw) in lines.enumerate() {
+        let line = n + 1;
+        match document_line(raw).finish() {
+            Ok((_, DocumentLine::Header(name))) => {
+                match groups.iter().position(|(g, _)| *g == name) {
+                    Some(pos) => {
+                        warnings.push(Warning::DuplicateGroup { group: name, line });
+                        current = Some(pos);
+                    }
+                    None => {
+                        groups.push((name, IndexMap::new()));
+                        current = Some(groups.len() - 1);
+                    }
+                }
+            }
+            Ok((_, DocumentLine::Line(Line::Entry(kv)))) => match current {
+                Some(idx) => {
+                    let (ref group, ref mut values) = groups[idx];
+                    let key = kv.key().to_owned();
+                    if values.insert(key, kv.value().into()).is_some() {
+                        warnings.push(Warning::DuplicateKey {
+                            group: group.clone(),
+                            key: kv.key().into(),
+                            line,
+                        });
+                    }
+                }
+                None => {
+                    let err = ParseError::from_error_kind(raw, ErrorKind::Tag);
+                    warnings.push(Warning::InvalidLine(err.on_line(line)));
+                }
+            },
+            Ok(_) => {}
+            Err(e) => {
+                let e = match current {
+                    Some(idx) => e.in_group(&groups[idx].0),
+                    None => e,
+                };
+                warnings.push(Warning::InvalidLine(e.on_line(line)));
+                if raw.starts_with(b"[") {
+                    // Don't add the entries of a broken group to the previous one
+                    current = None;
+                }
+            }
+        }
+    }
+    let groups = groups
+        .into_iter()
+        .map(|(name, values)| Group::new(name, values))
+        .collect();
+    let entry = DesktopEntry::new(groups);
+    // the Encoding key is only known once the rest of the file is parsed
+    #[cfg(feature = "encoding")]
+    if str::from_utf8(input).is_err()
+        && entry.main_group().and_then(|g| g.get_raw("Encoding")) == Some("Legacy-Mixed")
+    {
+        return parse_lenient(decode_latin1(input));
+    }
+    #[cfg(feature = "tracing")]
+    for warning in &warnings {
+        tracing::warn!(%warning, "problem in desktop entry");
+    }
+    (entry, warnings)
 }
 
-// If we ever support serialization, we need a way to preserve comments
-fn comment(i: &[u8]) -> IResult<&[u8]> {
-    let endline = char('\n').or(value('\0', eof));
-    delimited(char('#'), take_while(|c| c != b'\n'), endline)(i)
+fn restore_raw_bytes(entry: &mut DesktopEntry, values: Vec<(String, String, Option<Vec<u8>>)>) {
+    for (group, key, raw) in values {
+        if let Some(group) = entry.group_mut(&group) {
+            group.set_raw_bytes(&key, raw);
+        }
+    }
 }
-fn blanks(i: &[u8]) -> IResult<()> {
-    let empty_line = terminated(space0, char('\n'));
-    fold_many0(empty_line.or(comment), || (), |_, _| ())(i)
+
+/// Decode Latin-1, as its superset Windows-1252
+#[cfg(feature = "encoding")]
+fn decode_latin1(input: &[u8]) -> String {
+    let (decoded, _) = encoding_rs::WINDOWS_1252.decode_without_bom_handling(input);
+    decoded.into_owned()
 }
 
-fn key_value_list(i: &[u8]) -> IResult<HashMap<String, String>> {
-    fold_many0(
-        entry,
-        || HashMap::new(),
-        |mut acc, item| {
-            acc.insert(item.0, item.1);
-            acc
-        },
-    )(i)
+enum DocumentLine {
+    Header(String),
+    Line(Line),
 }
 
-fn entry(i: &[u8]) -> IResult<(String, String)> {
-    eprintln!("parsing entry: {}", str::from_utf8(i).unwrap_or(""));
-    separated_pair(
-        preceded(blanks, entry_key),
-        delimited(space0, char('='), space0),
-        entry_value,
-    )(i)
+fn document_line(i: &[u8]) -> IResult<'_, DocumentLine> {
+    let blank = map_res(all_consuming(space0), |s| {
+        str::from_utf8(s).map(|s| Line::Blank(s.into()))
+    });
+    let comment = map_res(preceded(char('#'), rest), |s| {
+        str::from_utf8(s).map(|s| Line::Comment(s.into()))
+    });
+    let header = map_res(all_consuming(header), |s| {
+        str::from_utf8(s).map(|s| DocumentLine::Header(s.into()))
+    });
+    alt((
+        header,
+        map(alt((blank, comment, document_entry)), DocumentLine::Line),
+    ))(i)
 }
 
-const KEY_RE: &'static str =
-    r"^[A-Za-z0-9-]+(\[[a-z]{2}(_[A-Z]{2})?(.[A-Za-z0-9-]+)?(@[A-Za-z09-]+)?\])?";
+fn document_entry(i: &[u8]) -> IResult<'_, Line> {
+    let (i, (key, separator, value)) = raw_entry(i)?;
+    let value = str::from_utf8(value)
+        .map_err(|_| nom::Err::Failure(ParseError::non_utf8(value).in_key(key)))?;
+    let kv = KeyValue::with_separator(
+        key.into(),
+        // separator is only made of ascii characters
+        String::from_utf8_lossy(separator).into_owned(),
+        value.into(),
+    );
+    Ok((i, Line::Entry(kv)))
+}
 
-fn entry_key(i: &[u8]) -> IResult<String> {
-    static RE_CELL: OnceCell<Regex> = OnceCell::new();
-    let key_re = RE_CELL.get_or_init(|| Regex::new(KEY_RE).unwrap());
+/// An entry line split into its key, separator and undecoded value
+fn raw_entry(i: &[u8]) -> IResult<'_, (&str, &[u8], &[u8])> {
+    let (i, key) = entry_key(i)?;
+    // without the `=` this isn't an entry, so the error isn't about the key
+    let (i, separator) = cut(recognize(delimited(space0, char('='), space0)))(i)?;
+    Ok((&i[i.len()..], (key, separator, i)))
+}
 
-    re_find(key_re.clone())
-        .map(|name| {
-            // regex already garantees name is ascii
-            let mut s = unsafe { str::from_utf8_unchecked(name) }.to_owned();
-            eprintln!("got name: {}", s);
-            // name is case-insensitive, so lowercase it
-            s.as_mut_str().make_ascii_lowercase();
-            s
-        })
-        .parse(i)
+fn desktop_entry(options: ParseOptions, input: &[u8]) -> IResult<'_, DesktopEntryRef<'_>> {
+    let (mut i, _) = blanks(options, input)?;
+    let mut groups: Vec<GroupRef> = Vec::new();
+    loop {
+        match group(options, i) {
+            Ok((rest, group)) => {
+                if options.strict && groups.iter().any(|g| g.name() == group.name()) {
+                    let err = ParseError::duplicate_group(i).in_group(group.name());
+                    return Err(nom::Err::Failure(err));
+                }
+                if options.compat == Compat::Glib {
+                    if let Some(existing) = groups.iter_mut().find(|g| g.name() == group.name()) {
+                        existing.merge(group);
+                        i = rest;
+                        continue;
+                    }
+                }
+                if let Some(max) = options.max_groups.filter(|&max| groups.len() >= max) {
+                    let err = ParseError::limit(Limit::Groups(max), i);
+                    return Err(nom::Err::Failure(err.in_group(group.name())));
+                }
+                groups.push(group);
+                i = rest;
+            }
+            Err(nom::Err::Error(_)) => return Ok((i, DesktopEntryRef::new(groups))),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn group(options: ParseOptions, i: &[u8]) -> IResult<'_, GroupRef<'_>> {
+    let indent = |i| indent(options, i);
+    let (i, name) = map_res(preceded(indent, header), str::from_utf8)(i)?;
+    // Anything other than the next group header after the entries is an invalid line in this group
+    let end_of_group = cut(peek(alt((eof, preceded(indent, tag("["))))));
+    let values = |i| key_value_list(options, i);
+    let line_end = |i| line_end(options, i);
+    let blanks = |i| blanks(options, i);
+    let (i, values) = terminated(delimited(line_end, values, blanks), end_of_group)(i)
+        .map_err(|e| e.map(|e| e.in_group(name)))?;
+    let mut group = GroupRef::with_values(name, values);
+    group.set_ignore_key_case(options.ignore_key_case);
+    group.set_lenient(options.lenient);
+    Ok((i, group))
 }
 
-fn entry_value(i: &[u8]) -> IResult<String> {
-    let (mut rest, line) = i.split_at_position_complete(|c| c == b'\n')?;
-    if !rest.is_empty() {
-        rest = &rest[1..];
+/// Skip blank lines and comments
+///
+/// This runs before every line, so it scans the input directly instead of
+/// combining parsers. Comments are dropped, `parse_document` keeps them.
+fn blanks(options: ParseOptions, mut i: &[u8]) -> IResult<'_, ()> {
+    loop {
+        let spaces = i.iter().take_while(|&&c| c == b' ' || c == b'\t').count();
+        if let Some(rest) = strip_line_end(options, &i[spaces..]) {
+            i = rest;
+            continue;
+        }
+        let line = match options.compat {
+            Compat::Glib => &i[spaces..],
+            Compat::Spec => i,
+        };
+        match line.strip_prefix(b"#") {
+            Some(comment) => {
+                i = match memchr(b'\n', comment) {
+                    Some(end) => &comment[end + 1..],
+                    None => &comment[comment.len()..],
+                }
+            }
+            None => return Ok((i, ())),
+        }
+    }
+}
+
+/// Leading whitespace, which is only allowed in glib compatibility mode
+fn indent(options: ParseOptions, i: &[u8]) -> IResult<'_, &[u8]> {
+    match options.compat {
+        Compat::Glib => space0(i),
+        Compat::Spec => Ok((i, &i[..0])),
+    }
+}
+
+fn line_end(options: ParseOptions, i: &[u8]) -> IResult<'_, char> {
+    match strip_line_end(options, i) {
+        Some(rest) => Ok((rest, '\n')),
+        None => char('\n')(i),
+    }
+}
+
+fn strip_line_end(options: ParseOptions, i: &[u8]) -> Option<&[u8]> {
+    match i.strip_prefix(b"\r\n") {
+        Some(rest) if options.crlf => Some(rest),
+        _ => i.strip_prefix(b"\n"),
+    }
+}
+
+fn key_value_list(options: ParseOptions, mut i: &[u8]) -> IResult<'_, Vec<(&str, ValueRef<'_>)>> {
+    let mut values: Vec<(&str, ValueRef)> = Vec::new();
+    loop {
+        // skip blank lines first, so a duplicate key is reported on the right line
+        let (start, _) = blanks(options, i)?;
+        match entry(options, start) {
+            Ok((rest, (key, value))) => {
+                if options.strict && values.iter().any(|(k, _)| *k == key) {
+                    let err = ParseError::duplicate_key(start).in_key(key);
+                    return Err(nom::Err::Failure(err));
+                }
+                if let Some(max) = options.max_keys.filter(|&max| values.len() >= max) {
+                    let err = ParseError::limit(Limit::Keys(max), start);
+                    return Err(nom::Err::Failure(err.in_key(key)));
+                }
+                values.push((key, value));
+                i = rest;
+            }
+            Err(nom::Err::Error(_)) => return Ok((i, values)),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn entry(options: ParseOptions, i: &[u8]) -> IResult<'_, (&str, ValueRef<'_>)> {
+    let key = match (options.mime_keys, options.compat) {
+        (true, _) => mime_key,
+        (false, Compat::Glib) => glib_key,
+        (false, Compat::Spec) => entry_key,
+    };
+    let (i, _) = blanks(options, i)?;
+    let (i, key) = preceded(|i| indent(options, i), key)(i)?;
+    let (i, _) = cut(delimited(space0, char('='), space0))(i)?;
+    let (i, value) = entry_value(options, i).map_err(|e| e.map(|e| e.in_key(key)))?;
+    Ok((i, (key, value)))
+}
+
+fn entry_key(i: &[u8]) -> IResult<'_, &str> {
+    let name = take_while1(is_key_char);
+    let locale = delimited(char('['), locale, char(']'));
+    map_res(recognize(pair(name, opt(locale))), str::from_utf8)(i)
+}
+
+/// A key as accepted by `GKeyFile`, which is anything up to the `=`
+fn glib_key(i: &[u8]) -> IResult<'_, &str> {
+    let (_, raw) = take_till1(|c| c == b'=' || c == b'\n')(i)?;
+    // whitespace before the `=` isn't part of the key
+    let len = raw.trim_ascii_end().len();
+    // a line starting with `[` is the next group header
+    if len == 0 || raw[0] == b'[' {
+        return Err(nom::Err::Error(ParseError::from_error_kind(
+            i,
+            ErrorKind::TakeTill1,
+        )));
+    }
+    map_res(take(len), str::from_utf8)(i)
+}
+
+fn mime_key(i: &[u8]) -> IResult<'_, &str> {
+    let is_mime_char = |c: u8| is_key_char(c) || b"/+._".contains(&c);
+    map_res(take_while1(is_mime_char), str::from_utf8)(i)
+}
+
+/// Parse a locale of the form `lang_COUNTRY.ENCODING@MODIFIER`
+///
+/// Only `lang` is required. Case isn't restricted, since real files use
+/// things like `sr_Latn` and `ca@valencia`.
+pub(crate) fn locale(i: &[u8]) -> IResult<'_, &str> {
+    let country = preceded(char('_'), alphanumeric1);
+    let encoding = preceded(char('.'), take_while1(is_key_char));
+    let modifier = preceded(char('@'), take_while1(is_key_char));
+    let locale = tuple((alpha1, opt(country), opt(encoding), opt(modifier)));
+    map_res(recognize(locale), str::from_utf8)(i)
+}
+
+fn is_key_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'-'
+}
+
+/// The rest of the line, keeping the original bytes if it isn't UTF-8
+fn entry_value(options: ParseOptions, i: &[u8]) -> IResult<'_, ValueRef<'_>> {
+    let (mut line, rest) = match memchr(b'\n', i) {
+        Some(end) => (&i[..end], &i[end + 1..]),
+        None => (i, &i[i.len()..]),
+    };
+    if options.crlf {
+        line = line.strip_suffix(b"\r").unwrap_or(line);
     }
     match str::from_utf8(line) {
-        //
-        Ok(line) => Ok((rest, line.to_string())),
-        Err(_) => Err(nom::Err::Failure(ParseError::NonUtf8)),
+        Ok(line) => Ok((rest, ValueRef::Str(line))),
+        Err(_) if options.replace_invalid => {
+            let replaced = String::from_utf8_lossy(line).into_owned();
+            Ok((
+                rest,
+                ValueRef::Invalid {
+                    replaced,
+                    raw: line,
+                },
+            ))
+        }
+        Err(_) => Err(nom::Err::Failure(ParseError::non_utf8(i))),
+    }
+}
+
+/// A group header such as `[Desktop Entry]`, returning the name
+fn header(i: &[u8]) -> IResult<'_, &[u8]> {
+    let (i, _) = char('[')(i)?;
+    // only the name up to the first `]` can be valid, so find that first
+    // and then check the characters in it
+    let end = memchr2(b']', b'\n', i).unwrap_or(i.len());
+    match i[..end].iter().position(|&c| !is_header_char(c)) {
+        Some(invalid) => Err(nom::Err::Error(ParseError::from_error_kind(
+            &i[invalid..],
+            ErrorKind::Char,
+        ))),
+        None => terminated(take(end), char(']'))(i),
     }
 }
 
 fn is_header_char(c: u8) -> bool {
     // any ASCII char that isn't a control acharacter
     // or a square bracket
-    c >= 32 && c < 127 && c != b'[' && c != b']'
+    (32..127).contains(&c) && c != b'[' && c != b']'
 }
 
 #[cfg(test)]
@@ -137,73 +855,143 @@ mod test {
         })
     }
 
+    #[test]
+    fn header_test() {
+        assert_eq!(
+            header(b"[Desktop Entry]\nKey=v"),
+            Ok((&b"\nKey=v"[..], &b"Desktop Entry"[..]))
+        );
+        assert_eq!(header(b"[]"), Ok((&b""[..], &b""[..])));
+        for invalid in [
+            &b"[a[b]"[..],
+            b"[a\tb]",
+            b"[a\nb]",
+            b"[unterminated",
+            b"Key=v",
+        ] {
+            assert!(header(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn blanks_test() {
+        let spec = ParseOptions::new();
+        let rest = |options, i| blanks(options, i).unwrap().0;
+        assert_eq!(rest(spec, b"\n \t\n# c\n#\nKey=v\n"), b"Key=v\n");
+        assert_eq!(rest(spec, b"# no newline"), b"");
+        assert_eq!(rest(spec, b"  \n  # indented"), b"  # indented");
+        assert_eq!(rest(spec, b"  "), b"  ");
+        assert_eq!(rest(spec, b"\r\nKey=v"), b"\r\nKey=v");
+        assert_eq!(rest(spec.crlf(true), b"\r\n# c\r\nKey=v"), b"Key=v");
+        let glib = spec.compat(Compat::Glib);
+        assert_eq!(rest(glib, b"  \n  # indented\n  Key=v"), b"  Key=v");
+    }
+
     #[test]
     fn entry_value_test_empty() {
-        assert_eq!(entry_value(&[][..]), Ok((&[][..], "".to_string())));
+        assert_eq!(
+            entry_value(ParseOptions::new(), &[][..]),
+            Ok((&[][..], ValueRef::Str("")))
+        );
     }
 
     #[test]
     fn entry_value_test_basic() {
         assert_eq!(
-            entry_value(&b"A simple value"[..]),
-            Ok((&b""[..], "A simple value".to_string()))
+            entry_value(ParseOptions::new(), &b"A simple value"[..]),
+            Ok((&b""[..], ValueRef::Str("A simple value")))
         );
         assert_eq!(
-            entry_value(&b"A simple value\n"[..]),
-            Ok((&b""[..], "A simple value".to_string()))
+            entry_value(ParseOptions::new(), &b"A simple value\n"[..]),
+            Ok((&b""[..], ValueRef::Str("A simple value")))
         );
     }
 
     #[test]
     fn entry_value_test_escapes() {
         assert_eq!(
-            entry_value(&b"\\s\\n\\t\\r\\\\\\a"[..]),
-            Ok((&b""[..], "\\s\\n\\t\\r\\\\\\a".to_string()))
+            entry_value(ParseOptions::new(), &b"\\s\\n\\t\\r\\\\\\a"[..]),
+            Ok((&b""[..], ValueRef::Str("\\s\\n\\t\\r\\\\\\a")))
         );
         assert_eq!(
-            entry_value(&b"Content with trailing slash \\"[..]),
-            Ok((&b""[..], "Content with trailing slash \\".to_string()))
+            entry_value(ParseOptions::new(), &b"Content with trailing slash \\"[..]),
+            Ok((&b""[..], ValueRef::Str("Content with trailing slash \\")))
         )
     }
 
+    #[test]
+    fn parse_io_test_invalid_utf8() {
+        let mut input = &b"[Desktop Entry]\nName=\xc0\xc1\n"[..];
+        assert!(matches!(parse_io(&mut input), Err(ParseError::NonUtf8(_))));
+    }
+
     #[test]
     fn entry_value_test_invalid_utf8() {
-        assert!(entry_value(&[0xc0, 0xc1]).is_err());
-        assert!(entry_value(&[0x80, 0xc1]).is_err());
+        assert!(entry_value(ParseOptions::new(), &[0xc0, 0xc1]).is_err());
+        assert!(entry_value(ParseOptions::new(), &[0x80, 0xc1]).is_err());
+        let options = ParseOptions::new().replace_invalid(true);
+        let replaced = "\u{fffd}a".to_string();
+        assert_eq!(
+            entry_value(options, &b"\xc0a\nb"[..]),
+            Ok((
+                &b"b"[..],
+                ValueRef::Invalid {
+                    replaced,
+                    raw: &b"\xc0a"[..]
+                }
+            ))
+        );
     }
 
     #[test]
     fn entry_key_test_locales() {
         assert_eq!(
             entry_key(&b"Name[en_US.UTF-8@shaw]"[..]),
-            Ok((&b""[..], "name[en_us.utf-8@shaw]".to_string()))
+            Ok((&b""[..], "Name[en_US.UTF-8@shaw]"))
         );
         assert_eq!(
             entry_key(&b"Name[en_US.UTF-8]"[..]),
-            Ok((&b""[..], "name[en_us.utf-8]".to_string()))
+            Ok((&b""[..], "Name[en_US.UTF-8]"))
         );
         assert_eq!(
             entry_key(&b"Name[en_US@shaw]"[..]),
-            Ok((&b""[..], "name[en_us@shaw]".to_string()))
+            Ok((&b""[..], "Name[en_US@shaw]"))
         );
         assert_eq!(
             entry_key(&b"Name[en.UTF-8@shaw]"[..]),
-            Ok((&b""[..], "name[en.utf-8@shaw]".to_string()))
+            Ok((&b""[..], "Name[en.UTF-8@shaw]"))
         );
         assert_eq!(
             entry_key(&b"Name[en_US]"[..]),
-            Ok((&b""[..], "name[en_us]".to_string()))
+            Ok((&b""[..], "Name[en_US]"))
         );
         assert_eq!(
             entry_key(&b"Name[en.UTF-8]"[..]),
-            Ok((&b""[..], "name[en.utf-8]".to_string()))
+            Ok((&b""[..], "Name[en.UTF-8]"))
         );
         assert_eq!(
             entry_key(&b"Name[en@shaw]"[..]),
-            Ok((&b""[..], "name[en@shaw]".to_string()))
+            Ok((&b""[..], "Name[en@shaw]"))
         );
     }
 
+    #[test]
+    fn entry_key_test_full_locales() {
+        for key in [
+            "Name[ca@valencia]",
+            "Name[sr_Latn]",
+            "Name[ast]",
+            "Name[EN_us]",
+            "Name[sr_RS.UTF-8@latin]",
+            "X-Vendor-Key2[de]",
+        ] {
+            assert_eq!(entry_key(key.as_bytes()), Ok((&b""[..], key)));
+        }
+        assert_eq!(entry_key(&b"Name[]"[..]), Ok((&b"[]"[..], "Name")));
+        assert_eq!(entry_key(&b"Name[en_]"[..]), Ok((&b"[en_]"[..], "Name")));
+        assert!(entry_key(&b"[Name]"[..]).is_err());
+    }
+
     #[test]
     fn entry_test() {
         let bytes = &b"\
@@ -220,14 +1008,17 @@ Value4=5.6"[..];
         let expected = DesktopEntry::new(vec![Group::new(
             "Desktop Entry".into(),
             hash! {
-                "value1".to_string() => "Some value".to_string(),
-                "value2".to_string() => "true".to_string(),
-                "value3".to_string() => "false".to_string(),
-                "value4".to_string() => "5.6".to_string()
+                "Value1".to_string() => "Some value".to_string(),
+                "Value2".to_string() => "true".to_string(),
+                "Value3".to_string() => "false".to_string(),
+                "Value4".to_string() => "5.6".to_string()
             },
         )]);
 
-        assert_eq!(desktop_entry(bytes), Ok((&b""[..], expected)));
+        assert_eq!(
+            desktop_entry(ParseOptions::default(), bytes).map(|(i, e)| (i, e.to_owned())),
+            Ok((&b""[..], expected))
+        );
     }
 
     #[test]
@@ -249,20 +1040,543 @@ Comment[de]=Zeug";
             Group::new(
                 "Desktop Entry".into(),
                 hash! {
-                    "exe".to_string() => "env A=a B=b sample-prog --foo --bar".to_string(),
-                    "directory".to_string() => "/etc/foo".to_string(),
-                    "enabled".to_string() => "true".to_string()
+                    "Exe".to_string() => "env A=a B=b sample-prog --foo --bar".to_string(),
+                    "Directory".to_string() => "/etc/foo".to_string(),
+                    "Enabled".to_string() => "true".to_string()
                 },
             ),
             Group::new(
                 "Sample".into(),
                 hash! {
-                    "comment".to_string() => "Stuff".to_string(),
-                    "comment[en]".to_string() => "Stuff".to_string(),
-                    "comment[de]".to_string() => "Zeug".to_string()
+                    "Comment".to_string() => "Stuff".to_string(),
+                    "Comment[en]".to_string() => "Stuff".to_string(),
+                    "Comment[de]".to_string() => "Zeug".to_string()
                 },
             ),
         ]);
         assert_eq!(parse(input).unwrap(), expected);
     }
+
+    #[test]
+    fn merge_test() {
+        let mut entry = parse(
+            "\
+[Desktop Entry]
+Name=Foo
+Exec=foo %U
+",
+        )
+        .unwrap();
+        let dropin = parse(
+            "\
+[Desktop Entry]
+Exec=foo --new-window %U
+OnlyShowIn=GNOME;
+
+[Extra]
+Key=Value
+",
+        )
+        .unwrap();
+        entry.merge(dropin);
+
+        let expected = DesktopEntry::new(vec![
+            Group::new(
+                "Desktop Entry".into(),
+                hash! {
+                    "Name".to_string() => "Foo".to_string(),
+                    "Exec".to_string() => "foo --new-window %U".to_string(),
+                    "OnlyShowIn".to_string() => "GNOME;".to_string()
+                },
+            ),
+            Group::new(
+                "Extra".into(),
+                hash! {
+                    "Key".to_string() => "Value".to_string()
+                },
+            ),
+        ]);
+        assert_eq!(entry, expected);
+    }
+
+    #[test]
+    fn parse_file_with_dropins_test() {
+        let dir = std::env::temp_dir().join(format!("dopen-dropins-{}", std::process::id()));
+        let dropins = dir.join("foo.desktop.d");
+        fs::create_dir_all(dropins.join("dir.conf")).unwrap();
+        let write = |path: std::path::PathBuf, contents: &str| fs::write(path, contents).unwrap();
+        write(
+            dir.join("foo.desktop"),
+            "[Desktop Entry]\nName=Foo\nExec=foo\n",
+        );
+        write(dropins.join("20-b.conf"), "[Desktop Entry]\nExec=b\n");
+        write(
+            dropins.join("10-a.conf"),
+            "[Desktop Entry]\nExec=a\nComment=A\n",
+        );
+        write(dropins.join("30-c.txt"), "[Desktop Entry]\nExec=ignored\n");
+        write(dir.join("bar.desktop"), "[Desktop Entry]\nName=Bar\n");
+
+        let entry = parse_file_with_dropins(dir.join("foo.desktop"));
+        let without_dropins = parse_file_with_dropins(dir.join("bar.desktop"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        // fragments are merged in lexical order, skipping anything but .conf files
+        let group = &entry.unwrap()["Desktop Entry"];
+        assert_eq!(group.get_raw("Name"), Some("Foo"));
+        assert_eq!(group.get_raw("Exec"), Some("b"));
+        assert_eq!(group.get_raw("Comment"), Some("A"));
+        let expected = parse("[Desktop Entry]\nName=Bar\n").unwrap();
+        assert_eq!(without_dropins.unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_error_location_test() {
+        let err = parse(&b"[Desktop Entry]\nName=Foo\nExec=foo\xff\n"[..]).unwrap_err();
+        let loc = err.location().unwrap();
+        assert!(matches!(err, ParseError::NonUtf8(_)));
+        assert_eq!(loc.group.as_deref(), Some("Desktop Entry"));
+        assert_eq!(loc.key.as_deref(), Some("Exec"));
+        assert_eq!(loc.line, 3);
+
+        // the line isn't an entry, so there is no key
+        let input = "[Desktop Entry]\nName=Foo\n\n[Other]\nnot an entry\n";
+        for err in [
+            parse(input).unwrap_err(),
+            parse_document(input).unwrap_err(),
+        ] {
+            let loc = err.location().unwrap();
+            assert_eq!(loc.group.as_deref(), Some("Other"));
+            assert_eq!(loc.key, None);
+            assert_eq!(loc.line, 5);
+        }
+    }
+
+    #[test]
+    fn document_round_trip_test() {
+        let input = "\
+# Leading comment
+
+[Desktop Entry]
+Type=Application
+#A comment
+Exec = sample-prog --foo
+Name[de]=Beispiel
+  \t
+Name=Sample
+
+[Desktop Action New]
+Exec=sample-prog --new";
+        let doc = parse_document(input).unwrap();
+        assert_eq!(doc.to_string(), input);
+        let with_newline = format!("{}\n", input);
+        assert_eq!(
+            parse_document(&with_newline).unwrap().to_string(),
+            with_newline
+        );
+
+        let keys: Vec<&str> = doc
+            .groups()
+            .next()
+            .unwrap()
+            .entries()
+            .map(|kv| kv.key())
+            .collect();
+        assert_eq!(keys, vec!["Type", "Exec", "Name[de]", "Name"]);
+        assert_eq!(
+            doc.to_entry().main_group().unwrap().get_raw("Exec"),
+            Some("sample-prog --foo")
+        );
+    }
+
+    #[test]
+    fn document_edit_test() {
+        let mut doc = parse_document(
+            "\
+[Desktop Entry]
+Exec = foo
+# trailing comment
+
+[Other]
+Key=Value
+",
+        )
+        .unwrap();
+        let group = doc.group_mut("Desktop Entry").unwrap();
+        group.set("Exec", "bar".into());
+        group.set("Name", "Bar".into());
+        assert_eq!(doc.remove_group("Other").unwrap().get("Key"), Some("Value"));
+        doc.group_or_insert("Desktop Action New")
+            .set("Exec", "bar --new".into());
+        assert_eq!(
+            doc.to_string(),
+            "\
+[Desktop Entry]
+Exec = bar
+Name=Bar
+# trailing comment
+
+[Desktop Action New]
+Exec=bar --new
+"
+        );
+        // a new document ends with a newline, however it is created
+        let mut doc = DesktopDocument::default();
+        doc.group_or_insert("Desktop Entry")
+            .set("Name", "Foo".into());
+        assert_eq!(doc.to_string(), "[Desktop Entry]\nName=Foo\n");
+    }
+
+    #[test]
+    fn document_error_test() {
+        let err = parse_document("[Desktop Entry]\nName=Foo\nbad line\n").unwrap_err();
+        let loc = err.location().unwrap();
+        assert_eq!(loc.group.as_deref(), Some("Desktop Entry"));
+        assert_eq!(loc.line, 3);
+        assert!(parse_document("Name=Foo\n").is_err());
+    }
+
+    #[test]
+    fn serialize_sorted_test() {
+        let input = "\
+[Desktop Entry]
+Name[fr]=Exemple
+NameX=x
+Exec=sample
+Name=Sample
+Name[de]=Beispiel
+
+[Desktop Action New]
+Name=New
+Exec=sample --new
+";
+        let expected = "\
+[Desktop Entry]
+Exec=sample
+Name=Sample
+Name[de]=Beispiel
+Name[fr]=Exemple
+NameX=x
+
+[Desktop Action New]
+Exec=sample --new
+Name=New
+";
+        let entry = parse(input).unwrap();
+        assert_eq!(entry.to_sorted_string(), expected);
+        let mut out = Vec::new();
+        entry.write_sorted_to(&mut out).unwrap();
+        assert_eq!(out, expected.as_bytes());
+    }
+
+    #[test]
+    fn serialize_round_trip_test() {
+        let entry = parse(
+            "\
+[Desktop Entry]
+Name=Sample\\sApp
+Exec=sample --foo
+Keywords=a;b\\;c;
+
+[Desktop Action New]
+Exec=sample --new
+",
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        entry.write_to(&mut out).unwrap();
+        assert_eq!(parse(&out).unwrap(), entry);
+
+        let raw_newline = DesktopEntry::new(vec![Group::new(
+            "Desktop Entry".into(),
+            hash! { "name".to_string() => "two\nlines".to_string() },
+        )]);
+        assert_eq!(
+            raw_newline.to_string(),
+            "[Desktop Entry]\nname=two\\nlines\n"
+        );
+    }
+
+    #[test]
+    fn parse_lenient_test() {
+        let input = &b"\
+stray=before group
+[Desktop Entry]
+Name=Foo
+this is junk
+Name=Bar
+Comment=\xff
+[Broken
+Exec=ignored
+[Desktop Entry]
+Exec=foo
+"[..];
+        let (entry, warnings) = parse_lenient(input);
+        let expected = DesktopEntry::new(vec![Group::new(
+            "Desktop Entry".into(),
+            hash! {
+                "Name".to_string() => "Bar".to_string(),
+                "Exec".to_string() => "foo".to_string()
+            },
+        )]);
+        assert_eq!(entry, expected);
+
+        let lines: Vec<usize> = warnings
+            .iter()
+            .map(|w| match w {
+                Warning::InvalidLine(e) => e.location().unwrap().line,
+                Warning::DuplicateKey { line, .. } | Warning::DuplicateGroup { line, .. } => *line,
+            })
+            .collect();
+        assert_eq!(lines, vec![1, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(
+            warnings[2],
+            Warning::DuplicateKey {
+                group: "Desktop Entry".into(),
+                key: "Name".into(),
+                line: 5
+            }
+        );
+    }
+
+    #[test]
+    fn strict_duplicates_test() {
+        let dup_key = "[Desktop Entry]\nName=Foo\n# comment\nName=Bar\n";
+        assert!(parse(dup_key).is_ok());
+        let err = ParseOptions::new().strict(true).parse(dup_key).unwrap_err();
+        assert!(matches!(err, ParseError::DuplicateKey(_)));
+        let loc = err.location().unwrap();
+        assert_eq!(loc.group.as_deref(), Some("Desktop Entry"));
+        assert_eq!(loc.key.as_deref(), Some("Name"));
+        assert_eq!(loc.line, 4);
+
+        let dup_group = "[Desktop Entry]\nName=Foo\n\n[A]\n\n[Desktop Entry]\nExec=foo\n";
+        assert_eq!(parse(dup_group).unwrap().groups().count(), 3);
+        let err = ParseOptions::new()
+            .strict(true)
+            .parse(dup_group)
+            .unwrap_err();
+        assert!(matches!(err, ParseError::DuplicateGroup(_)));
+        let loc = err.location().unwrap();
+        assert_eq!(loc.group.as_deref(), Some("Desktop Entry"));
+        assert_eq!(loc.line, 6);
+    }
+
+    #[test]
+    fn parse_borrowed_test() {
+        let input = "\
+[Desktop Entry]
+Name=Foo
+NAME[de]=Bar
+Exec=foo %U
+Exec=foo --new %U
+";
+        let entry = parse_borrowed(input).unwrap();
+        let group = entry.main_group().unwrap();
+        assert_eq!(group.name(), "Desktop Entry");
+        assert_eq!(group.get_raw("name[de]"), None);
+        assert_eq!(group.get_raw("NAME[de]"), Some("Bar"));
+        assert_eq!(group.get_raw("Exec"), Some("foo --new %U"));
+        let keys: Vec<&str> = group.values().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["Name", "NAME[de]", "Exec", "Exec"]);
+        assert_eq!(entry.to_owned(), parse(input).unwrap());
+    }
+
+    #[cfg(feature = "encoding")]
+    #[test]
+    fn legacy_encoding_test() {
+        let input = b"[Desktop Entry]\nEncoding=Legacy-Mixed\nName=Caf\xe9\n";
+        let entry = parse(input).unwrap();
+        assert_eq!(
+            entry.main_group().unwrap().get_raw("Name"),
+            Some("Caf\u{e9}")
+        );
+
+        let input = b"[Desktop Entry]\nName=Caf\xe9\n";
+        assert!(matches!(parse(input), Err(ParseError::NonUtf8(_))));
+
+        // the key is read like any other, wherever it is in the group
+        let input = &b"[Desktop Entry]\nName=Caf\xe9\r\nEncoding = Legacy-Mixed\r\n"[..];
+        let options = ParseOptions::new().crlf(true);
+        let entry = options.parse(input).unwrap();
+        assert_eq!(entry["Desktop Entry"].get_raw("Name"), Some("Caf\u{e9}"));
+        assert!(matches!(
+            options.parse_borrowed(input),
+            Err(ParseError::LegacyEncoding)
+        ));
+        let input = &b"[Desktop Entry]\nName=Caf\xe9\nEncoding = Legacy-Mixed\n"[..];
+        let (entry, warnings) = parse_lenient(input);
+        assert_eq!(entry["Desktop Entry"].get_raw("Name"), Some("Caf\u{e9}"));
+        assert!(warnings.is_empty());
+        let document = parse_document(input).unwrap();
+        let group = document.group("Desktop Entry").unwrap();
+        assert_eq!(group.get("Name"), Some("Caf\u{e9}"));
+
+        // only a key in the main group counts
+        let input = b"[Other]\nEncoding=Legacy-Mixed\nName=Caf\xe9\n";
+        assert!(matches!(parse(input), Err(ParseError::NonUtf8(_))));
+
+        // a stream is only transcoded after the key
+        let input = &b"[Desktop Entry]\nEncoding=Legacy-Mixed\nName=Caf\xe9\n"[..];
+        let entry = parse_stream(input).unwrap();
+        assert_eq!(entry["Desktop Entry"].get_raw("Name"), Some("Caf\u{e9}"));
+        let input = &b"[Desktop Entry]\nName=Caf\xe9\nEncoding=Legacy-Mixed\n"[..];
+        assert!(matches!(parse_stream(input), Err(ParseError::NonUtf8(_))));
+    }
+
+    #[test]
+    fn glib_compat_test() {
+        let input = "\
+  # indented comment
+  [Desktop Entry]
+  Name = Foo
+X-Odd.Key name=odd
+Exec=foo
+
+[X-Extra]
+A=1
+
+[Desktop Entry]
+Exec=foo --merged
+";
+        assert!(parse(input).is_err());
+        let options = ParseOptions::new().compat(Compat::Glib);
+        let entry = options.parse(input).unwrap();
+        assert_eq!(entry.groups().count(), 2);
+        let group = entry.main_group().unwrap();
+        assert_eq!(group.get_raw("Name"), Some("Foo"));
+        assert_eq!(group.get_raw("X-Odd.Key name"), Some("odd"));
+        assert_eq!(group.get_raw("Exec"), Some("foo --merged"));
+        assert_eq!(entry["X-Extra"].get_raw("A"), Some("1"));
+
+        assert!(options.parse("[Desktop Entry]\nNo separator\n").is_err());
+        assert!(options.strict(true).parse(input).is_err());
+    }
+
+    #[test]
+    fn ignore_key_case_test() {
+        let input = "[Desktop Entry]\nName=Foo\nNAME[de]=Bar\nname=foo\n";
+        let options = ParseOptions::new().strict(true).ignore_key_case(true);
+        let entry = options.parse_borrowed(input).unwrap();
+        let group = entry.main_group().unwrap();
+        assert_eq!(group.get_raw("name[de]"), Some("Bar"));
+        // an exact match is preferred
+        assert_eq!(group.get_raw("Name"), Some("Foo"));
+        assert_eq!(group.get_raw("name"), Some("foo"));
+
+        let entry = options.parse(input).unwrap();
+        assert_eq!(entry["Desktop Entry"].get_raw("name[DE]"), Some("Bar"));
+        assert_eq!(entry["Desktop Entry"].get_raw("Name"), Some("Foo"));
+        let entry = options.parse_stream(input.as_bytes()).unwrap();
+        assert_eq!(entry["Desktop Entry"].get_raw("name[DE]"), Some("Bar"));
+    }
+
+    #[test]
+    fn parse_stream_test() {
+        let input = "\
+# Comment
+[Desktop Entry]
+Name=Foo
+Exec = foo %U
+
+[Desktop Action New]
+Exec=foo --new
+";
+        assert_eq!(
+            parse_stream(input.as_bytes()).unwrap(),
+            parse(input).unwrap()
+        );
+
+        let err = parse_stream(&b"[Desktop Entry]\nName=Foo\nbad line\n"[..]).unwrap_err();
+        let loc = err.location().unwrap();
+        assert_eq!(loc.group.as_deref(), Some("Desktop Entry"));
+        assert_eq!(loc.line, 3);
+
+        let dup = &b"[Desktop Entry]\nName=Foo\nName=Bar\n"[..];
+        assert!(parse_stream(dup).is_ok());
+        let err = ParseOptions::new()
+            .strict(true)
+            .parse_stream(dup)
+            .unwrap_err();
+        assert!(matches!(err, ParseError::DuplicateKey(_)));
+    }
+
+    #[test]
+    fn parse_options_limits_test() {
+        let input = "[Desktop Entry]\nName=Foo\nExec=foo\n\n[A]\n";
+        let limited = |options: ParseOptions| {
+            let borrowed = options.parse(input).unwrap_err();
+            let streamed = options.parse_stream(input.as_bytes()).unwrap_err();
+            let read = options.parse_io(&mut input.as_bytes()).unwrap_err();
+            assert!(matches!(read, ParseError::LimitExceeded(..)));
+            assert_eq!(borrowed.to_string(), streamed.to_string());
+            borrowed
+        };
+        assert!(matches!(
+            limited(ParseOptions::new().max_size(10)),
+            ParseError::LimitExceeded(Limit::Size(10), _)
+        ));
+        assert!(matches!(
+            limited(ParseOptions::new().max_groups(1)),
+            ParseError::LimitExceeded(Limit::Groups(1), _)
+        ));
+        let err = limited(ParseOptions::new().max_keys(1));
+        assert!(matches!(err, ParseError::LimitExceeded(Limit::Keys(1), _)));
+        assert_eq!(err.location().unwrap().line, 3);
+
+        let options = ParseOptions::new()
+            .max_size(input.len())
+            .max_groups(2)
+            .max_keys(2);
+        assert!(options.parse(input).is_ok());
+        assert!(options.parse_stream(input.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn parse_options_encoding_test() {
+        let input = &b"\xef\xbb\xbf[Desktop Entry]\r\n# comment\r\n\r\nName=Foo\xff\r\n"[..];
+        assert!(parse(input).is_err());
+        let options = ParseOptions::new()
+            .bom(true)
+            .crlf(true)
+            .replace_invalid(true);
+        let mut group = Group::new(
+            "Desktop Entry".into(),
+            hash! { "Name".to_string() => "Foo\u{fffd}".to_string() },
+        );
+        group.set_raw_bytes("Name", Some(b"Foo\xff".to_vec()));
+        let expected = DesktopEntry::new(vec![group]);
+        assert_eq!(options.parse(input).unwrap(), expected);
+        assert_eq!(options.parse_stream(input).unwrap(), expected);
+        let entry = options.parse(input).unwrap();
+        let group = entry.main_group().unwrap();
+        assert_eq!(group.get_raw("Name"), Some("Foo\u{fffd}"));
+        assert_eq!(group.get_raw_bytes("Name"), Some(&b"Foo\xff"[..]));
+
+        // a later valid value replaces the invalid one
+        let input = &b"[Desktop Entry]\nName=Foo\xff\nName=Bar\n"[..];
+        let entry = options.parse(input).unwrap();
+        assert_eq!(
+            entry["Desktop Entry"].get_raw_bytes("Name"),
+            Some(&b"Bar"[..])
+        );
+        assert!(ParseOptions::new()
+            .bom(true)
+            .crlf(true)
+            .parse(input)
+            .is_err());
+
+        // comments aren't entries, even if they contain `=`
+        let input = &b"[Desktop Entry]\n#Name=\xfe\n  Name=Foo\xff\n"[..];
+        let options = options.compat(Compat::Glib);
+        let entry = options.parse(input).unwrap();
+        assert_eq!(
+            entry.main_group().unwrap().get_raw_bytes("Name"),
+            Some(&b"Foo\xff"[..])
+        );
+        let entry = options.parse_borrowed(input).unwrap();
+        let group = entry.main_group().unwrap();
+        assert_eq!(group.get_raw("Name"), Some("Foo\u{fffd}"));
+        assert_eq!(group.get_raw_bytes("Name"), Some(&b"Foo\xff"[..]));
+    }
 }
@@ -0,0 +1,184 @@
+//! Conversion between file paths and `file://` URIs
+//!
+//! These are the rules used for the `%u`/`%U` and `%f`/`%F` field codes:
+//! paths are UTF-8 percent-encoded as in RFC 3986, with every byte except
+//! unreserved characters and `/` escaped. Outside of Unix, paths that
+//! aren't valid Unicode can't be converted.
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+#[cfg(unix)]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{self, Path, PathBuf};
+
+const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+fn is_unreserved(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || b"-._~".contains(&c)
+}
+
+/// Percent-encode bytes, leaving unreserved characters and `/` as they are
+pub fn percent_encode(input: &[u8]) -> String {
+    let mut out = String::with_capacity(input.len());
+    for &c in input {
+        if is_unreserved(c) || c == b'/' {
+            out.push(c as char);
+        } else {
+            out.push('%');
+            out.push(HEX[(c >> 4) as usize] as char);
+            out.push(HEX[(c & 0xf) as usize] as char);
+        }
+    }
+    out
+}
+
+/// Decode percent-encoded bytes
+///
+/// Returns `None` if a `%` isn't followed by two hex digits.
+pub fn percent_decode(input: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut bytes = input.bytes();
+    while let Some(c) = bytes.next() {
+        if c == b'%' {
+            let hi = (bytes.next()? as char).to_digit(16)?;
+            let lo = (bytes.next()? as char).to_digit(16)?;
+            out.push((hi * 16 + lo) as u8);
+        } else {
+            out.push(c);
+        }
+    }
+    Some(out)
+}
+
+/// Check if a string starts with a URI scheme such as `file:` or `https:`
+pub fn has_scheme(s: &str) -> bool {
+    match s.split_once(':') {
+        Some((scheme, _)) => {
+            let mut chars = scheme.chars();
+            chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+                && chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        }
+        None => false,
+    }
+}
+
+/// Schemes whose URIs don't start with `scheme://`
+const OPAQUE_SCHEMES: &[&str] = &[
+    "about", "data", "geo", "magnet", "mailto", "news", "sip", "sms", "tel", "urn", "xmpp",
+];
+
+/// Check if a string looks like a URI rather than a path
+///
+/// Any `word:` prefix is a valid scheme, but that also matches paths such
+/// as `notes:2024.txt` or `C:\file`, so this requires a `scheme://` prefix
+/// or one of a few well known schemes without an authority, like `mailto:`.
+pub fn is_uri(s: &str) -> bool {
+    if !has_scheme(s) {
+        return false;
+    }
+    let (scheme, rest) = s.split_once(':').unwrap_or((s, ""));
+    rest.starts_with("//")
+        || OPAQUE_SCHEMES
+            .iter()
+            .any(|known| scheme.eq_ignore_ascii_case(known))
+}
+
+/// Convert a path to a `file://` URI
+///
+/// Relative paths are made absolute using the current directory.
+pub fn path_to_file_uri<P: AsRef<Path>>(path: P) -> String {
+    let path = path.as_ref();
+    let path = path::absolute(path).unwrap_or_else(|_| path.to_owned());
+    format!("file://{}", percent_encode(&os_str_bytes(path.as_os_str())))
+}
+
+/// Convert a `file://` URI to a path
+///
+/// Returns `None` for other schemes, URIs with a host other than
+/// `localhost`, and invalid percent-encoding.
+pub fn file_uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !path.starts_with('/') {
+        return None;
+    }
+    let path = path.split(['?', '#']).next().unwrap_or(path);
+    os_string_from_bytes(percent_decode(path)?).map(PathBuf::from)
+}
+
+#[cfg(unix)]
+fn os_str_bytes(s: &OsStr) -> Cow<'_, [u8]> {
+    Cow::Borrowed(s.as_bytes())
+}
+
+/// Elsewhere paths aren't bytes, so encode them as UTF-8
+#[cfg(not(unix))]
+fn os_str_bytes(s: &OsStr) -> Cow<'_, [u8]> {
+    match s.to_string_lossy() {
+        Cow::Borrowed(s) => Cow::Borrowed(s.as_bytes()),
+        Cow::Owned(s) => Cow::Owned(s.into_bytes()),
+    }
+}
+
+#[cfg(unix)]
+fn os_string_from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    Some(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn os_string_from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    String::from_utf8(bytes).ok().map(OsString::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_encode_test() {
+        assert_eq!(
+            percent_encode("/home/me/My Files/caf\u{e9}#1.txt".as_bytes()),
+            "/home/me/My%20Files/caf%C3%A9%231.txt"
+        );
+        assert_eq!(percent_encode(b"a-b_c.d~e\xff"), "a-b_c.d~e%FF");
+    }
+
+    #[test]
+    fn percent_decode_test() {
+        assert_eq!(
+            percent_decode("/My%20Files/caf%c3%A9").unwrap(),
+            "/My Files/caf\u{e9}".as_bytes()
+        );
+        assert_eq!(percent_decode("100%"), None);
+        assert_eq!(percent_decode("%zz"), None);
+    }
+
+    #[test]
+    fn file_uri_test() {
+        let path = Path::new("/tmp/a b/\u{e9}.txt");
+        let uri = path_to_file_uri(path);
+        assert_eq!(uri, "file:///tmp/a%20b/%C3%A9.txt");
+        assert_eq!(file_uri_to_path(&uri).as_deref(), Some(path));
+        assert_eq!(
+            file_uri_to_path("file://localhost/tmp/x").as_deref(),
+            Some(Path::new("/tmp/x"))
+        );
+        assert_eq!(file_uri_to_path("file://host/tmp/x"), None);
+        assert_eq!(file_uri_to_path("https://example.com/"), None);
+
+        assert!(has_scheme("https://example.com"));
+        assert!(has_scheme("mailto:me@example.com"));
+        assert!(!has_scheme("/tmp/a:b"));
+        assert!(!has_scheme("notes.txt"));
+    }
+
+    #[test]
+    fn is_uri_test() {
+        assert!(is_uri("https://example.com"));
+        assert!(is_uri("file:///tmp/x"));
+        assert!(is_uri("mailto:me@example.com"));
+        assert!(is_uri("MAILTO:me@example.com"));
+        assert!(!is_uri("notes:2024.txt"));
+        assert!(!is_uri("C:\\Users\\me"));
+        assert!(!is_uri("/tmp/a:b"));
+    }
+}
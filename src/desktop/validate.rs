@@ -0,0 +1,672 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use super::document::{DesktopDocument, Line};
+use super::entries::util::split_value_str;
+use super::entries::Category;
+use super::error::ValidationError;
+use super::model::{DesktopEntry, Group, DESKTOP_ENTRY_NAME};
+
+/// How serious a problem found by `DesktopEntry::validate` is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Not wrong, but could be improved
+    Hint,
+    /// Deprecated or suspicious, but still works
+    Warning,
+    /// Violates the spec
+    Error,
+}
+
+/// A problem found by `DesktopEntry::validate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The group the problem is in, if any
+    pub group: Option<String>,
+    /// The key the problem is with, if any
+    pub key: Option<String>,
+    /// The line of the key or group, starting at 1
+    ///
+    /// This is only known when validating a `DesktopDocument`.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Severity::Hint => "hint",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.severity)?;
+        match (&self.group, &self.key) {
+            (Some(g), Some(k)) => write!(f, "[{}] {}: ", g, k)?,
+            (Some(g), None) => write!(f, "[{}]: ", g)?,
+            _ => {}
+        }
+        f.write_str(&self.message)?;
+        match self.line {
+            Some(line) => write!(f, " (line {})", line),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    String,
+    LocaleString,
+    Strings,
+    LocaleStrings,
+    Bool,
+}
+
+/// Keys defined by the spec, with their type and the version that added them
+const KEYS: &[(&str, Kind, (u32, u32))] = &[
+    ("Type", Kind::String, (1, 0)),
+    ("Version", Kind::String, (1, 0)),
+    ("Name", Kind::LocaleString, (1, 0)),
+    ("GenericName", Kind::LocaleString, (1, 0)),
+    ("NoDisplay", Kind::Bool, (1, 0)),
+    ("Comment", Kind::LocaleString, (1, 0)),
+    ("Icon", Kind::LocaleString, (1, 0)),
+    ("Hidden", Kind::Bool, (1, 0)),
+    ("OnlyShowIn", Kind::Strings, (1, 0)),
+    ("NotShowIn", Kind::Strings, (1, 0)),
+    ("DBusActivatable", Kind::Bool, (1, 1)),
+    ("TryExec", Kind::String, (1, 0)),
+    ("Exec", Kind::String, (1, 0)),
+    ("Path", Kind::String, (1, 0)),
+    ("Terminal", Kind::Bool, (1, 0)),
+    ("Actions", Kind::Strings, (1, 1)),
+    ("MimeType", Kind::Strings, (1, 0)),
+    ("Categories", Kind::Strings, (1, 0)),
+    ("Implements", Kind::Strings, (1, 1)),
+    ("Keywords", Kind::LocaleStrings, (1, 1)),
+    ("StartupNotify", Kind::Bool, (1, 0)),
+    ("StartupWMClass", Kind::String, (1, 0)),
+    ("URL", Kind::String, (1, 0)),
+    ("PrefersNonDefaultGPU", Kind::Bool, (1, 4)),
+    ("SingleMainWindow", Kind::Bool, (1, 5)),
+];
+
+/// Keys that were in earlier versions of the spec, or only used by KDE
+const DEPRECATED_KEYS: &[&str] = &[
+    "Encoding",
+    "MiniIcon",
+    "TerminalOptions",
+    "Protocols",
+    "Extensions",
+    "BinaryPattern",
+    "MapNotify",
+    "SwallowTitle",
+    "SwallowExec",
+    "SortOrder",
+    "FilePattern",
+    "Dev",
+    "FSType",
+    "MountPoint",
+    "ReadOnly",
+    "UnmountIcon",
+];
+
+const ACTION_KEYS: &[&str] = &["Name", "Icon", "Exec"];
+
+impl DesktopEntry {
+    /// Check the entry against a version of the spec, such as `SPEC_VERSION`
+    ///
+    /// This covers most of the checks done by `desktop-file-validate`:
+    /// required keys, value types, unknown and deprecated keys, and the
+    /// group layout. An empty result means the entry is valid.
+    pub fn validate(&self, spec_version: &str) -> Vec<Diagnostic> {
+        let mut validator = Validator {
+            version: parse_version(spec_version).unwrap_or((1, 0)),
+            diagnostics: Vec::new(),
+        };
+        validator.check_entry(self);
+        validator.diagnostics
+    }
+
+    /// Validate the entry, failing if any problem violates the spec
+    ///
+    /// Hints and warnings don't make the check fail, but are kept in the
+    /// error alongside the errors.
+    pub fn check_valid(&self, spec_version: &str) -> Result<(), ValidationError> {
+        let diagnostics = self.validate(spec_version);
+        if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            Err(ValidationError { diagnostics })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl DesktopDocument {
+    /// Check the document like `DesktopEntry::validate`, including the line
+    /// of each problem
+    pub fn validate(&self, spec_version: &str) -> Vec<Diagnostic> {
+        let mut diagnostics = self.to_entry().validate(spec_version);
+        for diagnostic in &mut diagnostics {
+            let group = diagnostic.group.as_deref();
+            diagnostic.line = find_line(self, group, diagnostic.key.as_deref());
+        }
+        diagnostics
+    }
+}
+
+/// The line of a group header, or of the last value of a key in a group
+fn find_line(document: &DesktopDocument, group: Option<&str>, key: Option<&str>) -> Option<usize> {
+    let mut line = document.preamble().len();
+    let mut found = None;
+    for g in document.groups() {
+        line += 1;
+        if Some(g.name()) == group {
+            let Some(key) = key else {
+                return Some(line);
+            };
+            for (n, l) in g.lines().enumerate() {
+                if matches!(l, Line::Entry(kv) if kv.key() == key) {
+                    found = Some(line + n + 1);
+                }
+            }
+        }
+        line += g.lines().len();
+    }
+    found
+}
+
+struct Validator {
+    version: (u32, u32),
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator {
+    fn report(&mut self, severity: Severity, group: &str, key: Option<&str>, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            group: Some(group.into()),
+            key: key.map(Into::into),
+            line: None,
+            message,
+        });
+    }
+
+    fn check_entry(&mut self, entry: &DesktopEntry) {
+        let main = match entry.groups().next() {
+            Some(group) if group.name() == DESKTOP_ENTRY_NAME => group,
+            _ => {
+                self.diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    group: None,
+                    key: None,
+                    line: None,
+                    message: format!("first group must be [{}]", DESKTOP_ENTRY_NAME),
+                });
+                match entry.main_group() {
+                    Some(group) => group,
+                    None => return,
+                }
+            }
+        };
+        self.check_main_group(main);
+        let actions: Vec<String> = main.get_raw("Actions").map_or(Vec::new(), |a| {
+            split_value_str(a).filter(|a| !a.is_empty()).collect()
+        });
+        for group in entry.groups().filter(|g| g.name() != DESKTOP_ENTRY_NAME) {
+            match group.name().strip_prefix("Desktop Action ") {
+                Some(id) if actions.iter().any(|a| a == id) => self.check_action(group),
+                Some(id) => self.report(
+                    Severity::Warning,
+                    group.name(),
+                    None,
+                    format!("action {} is not listed in the Actions key", id),
+                ),
+                None if group.name().starts_with("X-") => {}
+                None => self.report(
+                    Severity::Error,
+                    group.name(),
+                    None,
+                    "unknown group, custom groups must start with X-".into(),
+                ),
+            }
+        }
+        for id in actions {
+            if entry.action_group(&id).is_none() {
+                self.report(
+                    Severity::Error,
+                    DESKTOP_ENTRY_NAME,
+                    Some("Actions"),
+                    format!("action {} has no [Desktop Action {}] group", id, id),
+                );
+            }
+        }
+    }
+
+    fn check_main_group(&mut self, group: &Group) {
+        let name = group.name();
+        let ty = group.get_raw("Type");
+        match ty {
+            None => self.report(
+                Severity::Error,
+                name,
+                None,
+                "missing required key Type".into(),
+            ),
+            Some("Application" | "Link" | "Directory") => {}
+            Some(ty @ ("FSDevice" | "MimeType")) => self.report(
+                Severity::Warning,
+                name,
+                Some("Type"),
+                format!("type {} is deprecated", ty),
+            ),
+            Some(ty) => self.report(
+                Severity::Error,
+                name,
+                Some("Type"),
+                format!("unknown type {}", ty),
+            ),
+        }
+        if group.get_raw("Name").is_none() {
+            self.report(
+                Severity::Error,
+                name,
+                None,
+                "missing required key Name".into(),
+            );
+        }
+        let dbus = group.get_raw("DBusActivatable") == Some("true");
+        match ty {
+            Some("Application") if group.get_raw("Exec").is_none() && !dbus => self.report(
+                Severity::Error,
+                name,
+                None,
+                "applications must have an Exec key unless DBusActivatable is true".into(),
+            ),
+            Some("Link") if group.get_raw("URL").is_none() => self.report(
+                Severity::Error,
+                name,
+                None,
+                "missing required key URL for a Link".into(),
+            ),
+            Some(ty) if ty != "Link" && group.get_raw("URL").is_some() => self.report(
+                Severity::Error,
+                name,
+                Some("URL"),
+                "URL is only allowed for a Link".into(),
+            ),
+            _ => {}
+        }
+        if ty == Some("Link") && group.get_raw("Exec").is_some() {
+            let msg = "Exec is not allowed for a Link".into();
+            self.report(Severity::Error, name, Some("Exec"), msg);
+        }
+        for (key, value) in group.values() {
+            self.check_key(group, key, value);
+        }
+        if let Some(categories) = group.get_raw("Categories") {
+            self.check_categories(group, categories, ty);
+        }
+    }
+
+    fn check_key(&mut self, group: &Group, key: &str, value: &str) {
+        self.check_default_value(group, key);
+        let base = key.split_once('[').map_or(key, |(base, _)| base);
+        if base.starts_with("X-") {
+            return;
+        }
+        if DEPRECATED_KEYS.contains(&base) {
+            let msg = format!("key {} is deprecated", base);
+            self.report(Severity::Warning, group.name(), Some(key), msg);
+            return;
+        }
+        let Some(&(_, kind, since)) = KEYS.iter().find(|(k, _, _)| *k == base) else {
+            let mut msg = format!("unknown key {}, custom keys must start with X-", base);
+            if let Some(suggestion) = suggest_key(base) {
+                msg += &format!(" (did you mean {}?)", suggestion);
+            }
+            self.report(Severity::Error, group.name(), Some(key), msg);
+            return;
+        };
+        if since > self.version {
+            let msg = format!(
+                "key {} requires version {}.{} of the spec",
+                base, since.0, since.1
+            );
+            self.report(Severity::Warning, group.name(), Some(key), msg);
+        }
+        match kind {
+            Kind::Bool => self.check_bool(group, key, value),
+            Kind::Strings | Kind::LocaleStrings if !value.is_empty() && !value.ends_with(';') => {
+                let msg = "list values should end with a semicolon".into();
+                self.report(Severity::Hint, group.name(), Some(key), msg);
+            }
+            _ => {}
+        }
+    }
+
+    /// Check that a localized key such as `Name[de]` also has a value without
+    /// a locale, which is used when no translation matches
+    fn check_default_value(&mut self, group: &Group, key: &str) {
+        let Some((base, _)) = key.split_once('[') else {
+            return;
+        };
+        if group.get_raw(base).is_none() {
+            let msg = format!("localized key {} has no default {} value", key, base);
+            self.report(Severity::Error, group.name(), Some(key), msg);
+        }
+    }
+
+    fn check_bool(&mut self, group: &Group, key: &str, value: &str) {
+        match value {
+            "true" | "false" => {}
+            "0" | "1" => {
+                let msg = format!("boolean value {} is deprecated, use true or false", value);
+                self.report(Severity::Warning, group.name(), Some(key), msg);
+            }
+            _ => {
+                let msg = format!("invalid boolean value {}", value);
+                self.report(Severity::Error, group.name(), Some(key), msg);
+            }
+        }
+    }
+
+    fn check_categories(&mut self, group: &Group, categories: &str, ty: Option<&str>) {
+        let mut seen = HashSet::new();
+        let mut has_main = false;
+        for category in split_value_str(categories).filter(|c| !c.is_empty()) {
+            if !seen.insert(category.clone()) {
+                let msg = format!("category {} is listed more than once", category);
+                self.report(Severity::Warning, group.name(), Some("Categories"), msg);
+            }
+            has_main |= Category::from_str(&category).is_ok_and(|c| c.is_main());
+        }
+        if ty == Some("Application") && !seen.is_empty() && !has_main {
+            let msg = "no main category, the application may not appear in menus".into();
+            self.report(Severity::Hint, group.name(), Some("Categories"), msg);
+        }
+    }
+
+    fn check_action(&mut self, group: &Group) {
+        if group.get_raw("Name").is_none() {
+            let msg = "missing required key Name".into();
+            self.report(Severity::Error, group.name(), None, msg);
+        }
+        for key in group.values().keys() {
+            self.check_default_value(group, key);
+            let base = key.split_once('[').map_or(key.as_str(), |(base, _)| base);
+            if !ACTION_KEYS.contains(&base) && !base.starts_with("X-") {
+                let msg = format!("key {} is not allowed in an action", base);
+                self.report(Severity::Error, group.name(), Some(key), msg);
+            }
+        }
+    }
+}
+
+/// Find the standard key closest to a misspelled one, if any is close enough
+fn suggest_key(key: &str) -> Option<&'static str> {
+    let max_distance = (key.chars().count() / 3).max(1);
+    KEYS.iter()
+        .map(|&(k, _, _)| (edit_distance(key, k), k))
+        .filter(|&(d, _)| d <= max_distance)
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, k)| k)
+}
+
+/// The Levenshtein distance between two strings, ignoring ASCII case
+/// differences
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().map(|c| c.to_ascii_lowercase()).enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let (major, minor) = version.split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, parse_document, SPEC_VERSION};
+
+    fn validate(input: &str) -> Vec<(Severity, Option<String>, String)> {
+        let mut diagnostics: Vec<_> = parse(input)
+            .unwrap()
+            .validate(SPEC_VERSION)
+            .into_iter()
+            .map(|d| (d.severity, d.key, d.message))
+            .collect();
+        diagnostics.sort();
+        diagnostics
+    }
+
+    #[test]
+    fn valid_test() {
+        let input = "\
+[Desktop Entry]
+Type=Application
+Name=Sample
+Name[de]=Beispiel
+Exec=sample %U
+Categories=Utility;
+Actions=new;
+X-Custom=1
+
+[Desktop Action new]
+Name=New
+Exec=sample --new
+
+[X-Extra]
+Anything=goes
+";
+        assert_eq!(validate(input), []);
+    }
+
+    #[test]
+    fn check_valid_test() {
+        let warned = parse("[Desktop Entry]\nType=Application\nName=A\nExec=a\nEncoding=UTF-8\n");
+        assert_eq!(warned.unwrap().check_valid(SPEC_VERSION), Ok(()));
+
+        let entry = parse("[Desktop Entry]\nType=Link\nName=A\nExec=a\nEncoding=UTF-8\n").unwrap();
+        let err = entry.check_valid(SPEC_VERSION).unwrap_err();
+        assert_eq!(err.diagnostics.len(), 3);
+        assert_eq!(err.errors().count(), 2);
+        assert!(matches!(crate::Error::from(err), crate::Error::Validate(_)));
+    }
+
+    #[test]
+    fn invalid_test() {
+        let input = "\
+[Desktop Entry]
+Type=Link
+Exec=sample
+Terminal=yes
+NoDisplay=1
+Encoding=UTF-8
+Frobnicate=true
+Categories=Foo;Foo;
+Actions=gone;
+
+[Desktop Action other]
+Name=Other
+
+[Extra]
+";
+        let d = |s, k: Option<&str>, m: &str| (s, k.map(Into::into), m.into());
+        assert_eq!(
+            validate(input),
+            [
+                d(
+                    Severity::Warning,
+                    None,
+                    "action other is not listed in the Actions key"
+                ),
+                d(
+                    Severity::Warning,
+                    Some("Categories"),
+                    "category Foo is listed more than once"
+                ),
+                d(
+                    Severity::Warning,
+                    Some("Encoding"),
+                    "key Encoding is deprecated"
+                ),
+                d(
+                    Severity::Warning,
+                    Some("NoDisplay"),
+                    "boolean value 1 is deprecated, use true or false"
+                ),
+                d(Severity::Error, None, "missing required key Name"),
+                d(Severity::Error, None, "missing required key URL for a Link"),
+                d(
+                    Severity::Error,
+                    None,
+                    "unknown group, custom groups must start with X-"
+                ),
+                d(
+                    Severity::Error,
+                    Some("Actions"),
+                    "action gone has no [Desktop Action gone] group"
+                ),
+                d(
+                    Severity::Error,
+                    Some("Exec"),
+                    "Exec is not allowed for a Link"
+                ),
+                d(
+                    Severity::Error,
+                    Some("Frobnicate"),
+                    "unknown key Frobnicate, custom keys must start with X-"
+                ),
+                d(
+                    Severity::Error,
+                    Some("Terminal"),
+                    "invalid boolean value yes"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn localized_default_test() {
+        let input = "\
+[Desktop Entry]
+Type=Application
+Name[de]=Beispiel
+Comment=Sample
+Comment[fr]=Exemple
+Exec=sample
+X-Vendor-Title[de]=Titel
+Actions=new;
+
+[Desktop Action new]
+Name=New
+Icon[de]=neu
+";
+        let d = |k: &str, m: &str| (Severity::Error, Some(k.into()), m.into());
+        assert_eq!(
+            validate(input),
+            [
+                (Severity::Error, None, "missing required key Name".into()),
+                d(
+                    "Icon[de]",
+                    "localized key Icon[de] has no default Icon value"
+                ),
+                d(
+                    "Name[de]",
+                    "localized key Name[de] has no default Name value"
+                ),
+                d(
+                    "X-Vendor-Title[de]",
+                    "localized key X-Vendor-Title[de] has no default X-Vendor-Title value"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn suggest_key_test() {
+        assert_eq!(suggest_key("Commet"), Some("Comment"));
+        assert_eq!(suggest_key("name"), Some("Name"));
+        assert_eq!(suggest_key("Catagories"), Some("Categories"));
+        assert_eq!(suggest_key("StartupWmClass"), Some("StartupWMClass"));
+        assert_eq!(suggest_key("Frobnicate"), None);
+        assert_eq!(suggest_key("Foo"), None);
+
+        let entry = parse("[Desktop Entry]\nType=Directory\nName=Dir\nComent=x\n").unwrap();
+        assert_eq!(
+            entry.validate(SPEC_VERSION)[0].message,
+            "unknown key Coment, custom keys must start with X- (did you mean Comment?)"
+        );
+    }
+
+    #[test]
+    fn first_group_test() {
+        let entry = parse("[X-Other]\n\n[Desktop Entry]\nType=Directory\nName=Dir\n").unwrap();
+        let diagnostics = entry.validate("1.0");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error: first group must be [Desktop Entry]"
+        );
+    }
+
+    #[test]
+    fn document_lines_test() {
+        let document = parse_document(
+            "\
+# comment
+[Desktop Entry]
+Type=Application
+Name=App
+
+Terminal=yes
+Exec=app
+
+[Extra]
+",
+        )
+        .unwrap();
+        let lines: Vec<_> = document
+            .validate(SPEC_VERSION)
+            .into_iter()
+            .map(|d| d.line)
+            .collect();
+        assert_eq!(lines, [Some(6), Some(9)]);
+        assert_eq!(
+            document.validate(SPEC_VERSION)[0].to_string(),
+            "error: [Desktop Entry] Terminal: invalid boolean value yes (line 6)"
+        );
+    }
+
+    #[test]
+    fn key_version_test() {
+        let entry =
+            parse("[Desktop Entry]\nType=Application\nName=App\nExec=app\nSingleMainWindow=true\n")
+                .unwrap();
+        assert!(entry.validate(SPEC_VERSION).is_empty());
+        let diagnostics = entry.validate("1.4");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "key SingleMainWindow requires version 1.5 of the spec"
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! The XDG base directories
+//!
+//! See the [XDG Base Directory spec](https://specifications.freedesktop.org/basedir-spec/latest/)
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// The base directories to read and write files in
+///
+/// `BaseDirs::from_env` computes them like the spec describes. The fields
+/// are public, so a custom instance can be built for tests. The directories
+/// in the home directory are `None` if the home directory isn't known.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct BaseDirs {
+    pub home: Option<PathBuf>,
+    /// `$XDG_DATA_HOME`, `~/.local/share` by default
+    pub data_home: Option<PathBuf>,
+    /// `$XDG_CONFIG_HOME`, `~/.config` by default
+    pub config_home: Option<PathBuf>,
+    /// `$XDG_CACHE_HOME`, `~/.cache` by default
+    pub cache_home: Option<PathBuf>,
+    /// `$XDG_STATE_HOME`, `~/.local/state` by default
+    pub state_home: Option<PathBuf>,
+    /// `$XDG_RUNTIME_DIR`, which has no default
+    pub runtime_dir: Option<PathBuf>,
+    /// `$XDG_DATA_DIRS` in order of preference, `/usr/local/share` and
+    /// `/usr/share` by default
+    pub data_dirs: Vec<PathBuf>,
+    /// `$XDG_CONFIG_DIRS` in order of preference, `/etc/xdg` by default
+    pub config_dirs: Vec<PathBuf>,
+}
+
+impl BaseDirs {
+    /// The base directories from the environment of the current process
+    pub fn from_env() -> BaseDirs {
+        BaseDirs::from_vars(|name| env::var_os(name))
+    }
+
+    /// The base directories from environment variables looked up with `var`
+    ///
+    /// Unset or empty variables use the default from the spec. Relative
+    /// paths are invalid and ignored.
+    pub fn from_vars<F: Fn(&str) -> Option<OsString>>(var: F) -> BaseDirs {
+        let home = var("HOME").map(PathBuf::from).filter(|p| p.is_absolute());
+        let home_dir = |name: &str, default: &str| {
+            absolute_dir(var(name)).or_else(|| Some(home.as_ref()?.join(default)))
+        };
+        BaseDirs {
+            data_home: home_dir("XDG_DATA_HOME", ".local/share"),
+            config_home: home_dir("XDG_CONFIG_HOME", ".config"),
+            cache_home: home_dir("XDG_CACHE_HOME", ".cache"),
+            state_home: home_dir("XDG_STATE_HOME", ".local/state"),
+            runtime_dir: absolute_dir(var("XDG_RUNTIME_DIR")),
+            data_dirs: dir_list(var("XDG_DATA_DIRS"), &["/usr/local/share", "/usr/share"]),
+            config_dirs: dir_list(var("XDG_CONFIG_DIRS"), &["/etc/xdg"]),
+            home,
+        }
+    }
+
+    /// The same directories inside `root`, such as a sysroot or a chroot
+    pub fn in_root<P: AsRef<Path>>(&self, root: P) -> BaseDirs {
+        let root = root.as_ref();
+        let map = |path: &PathBuf| root.join(path.strip_prefix("/").unwrap_or(path));
+        BaseDirs {
+            home: self.home.as_ref().map(map),
+            data_home: self.data_home.as_ref().map(map),
+            config_home: self.config_home.as_ref().map(map),
+            cache_home: self.cache_home.as_ref().map(map),
+            state_home: self.state_home.as_ref().map(map),
+            runtime_dir: self.runtime_dir.as_ref().map(map),
+            data_dirs: self.data_dirs.iter().map(map).collect(),
+            config_dirs: self.config_dirs.iter().map(map).collect(),
+        }
+    }
+
+    /// The data directories to search, starting with `data_home`
+    pub fn data_search_dirs(&self) -> impl Iterator<Item = &Path> {
+        self.data_home
+            .iter()
+            .chain(&self.data_dirs)
+            .map(PathBuf::as_path)
+    }
+
+    /// The config directories to search, starting with `config_home`
+    pub fn config_search_dirs(&self) -> impl Iterator<Item = &Path> {
+        self.config_home
+            .iter()
+            .chain(&self.config_dirs)
+            .map(PathBuf::as_path)
+    }
+
+    /// The `applications` directories that desktop files are installed in,
+    /// in order of preference
+    pub fn applications_dirs(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.data_search_dirs().map(|d| d.join("applications"))
+    }
+
+    /// The `mimeapps.list` files, in order of preference
+    ///
+    /// Desktop specific files such as `gnome-mimeapps.list` aren't included.
+    pub fn mimeapps_lists(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        let config = self.config_search_dirs().map(|d| d.join("mimeapps.list"));
+        let data = self.applications_dirs().map(|d| d.join("mimeapps.list"));
+        config.chain(data)
+    }
+}
+
+fn absolute_dir(value: Option<OsString>) -> Option<PathBuf> {
+    value.map(PathBuf::from).filter(|p| p.is_absolute())
+}
+
+fn dir_list(value: Option<OsString>, default: &[&str]) -> Vec<PathBuf> {
+    let dirs: Vec<PathBuf> = value
+        .map(|v| env::split_paths(&v).filter(|p| p.is_absolute()).collect())
+        .unwrap_or_default();
+    if dirs.is_empty() {
+        default.iter().map(PathBuf::from).collect()
+    } else {
+        dirs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn from_vars(vars: &[(&str, &str)]) -> BaseDirs {
+        let vars: HashMap<_, _> = vars.iter().copied().collect();
+        BaseDirs::from_vars(|name| vars.get(name).map(OsString::from))
+    }
+
+    #[test]
+    fn base_dirs_test() {
+        let dirs = from_vars(&[("HOME", "/home/user")]);
+        assert_eq!(dirs.data_home, Some("/home/user/.local/share".into()));
+        assert_eq!(dirs.config_home, Some("/home/user/.config".into()));
+        assert_eq!(dirs.cache_home, Some("/home/user/.cache".into()));
+        assert_eq!(dirs.state_home, Some("/home/user/.local/state".into()));
+        assert_eq!(dirs.runtime_dir, None);
+        assert_eq!(
+            dirs.data_dirs,
+            [Path::new("/usr/local/share"), Path::new("/usr/share")]
+        );
+        assert_eq!(dirs.config_dirs, [Path::new("/etc/xdg")]);
+
+        let dirs = from_vars(&[
+            ("HOME", "/home/user"),
+            ("XDG_DATA_HOME", "relative/data"),
+            ("XDG_CONFIG_HOME", "/cfg"),
+            ("XDG_CACHE_HOME", ""),
+            ("XDG_RUNTIME_DIR", "/run/user/1000"),
+            ("XDG_DATA_DIRS", "/opt/share:relative:/usr/share"),
+            ("XDG_CONFIG_DIRS", "relative"),
+        ]);
+        assert_eq!(dirs.data_home, Some("/home/user/.local/share".into()));
+        assert_eq!(dirs.config_home, Some("/cfg".into()));
+        assert_eq!(dirs.cache_home, Some("/home/user/.cache".into()));
+        assert_eq!(dirs.runtime_dir, Some("/run/user/1000".into()));
+        assert_eq!(
+            dirs.data_dirs,
+            [Path::new("/opt/share"), Path::new("/usr/share")]
+        );
+        assert_eq!(dirs.config_dirs, [Path::new("/etc/xdg")]);
+
+        let dirs = from_vars(&[]);
+        assert_eq!(dirs.data_home, None);
+        assert_eq!(dirs.data_search_dirs().count(), 2);
+    }
+
+    #[test]
+    fn search_paths_test() {
+        let dirs = from_vars(&[("HOME", "/home/user"), ("XDG_DATA_DIRS", "/usr/share")])
+            .in_root("/sysroot");
+        let apps: Vec<_> = dirs.applications_dirs().collect();
+        assert_eq!(
+            apps,
+            [
+                Path::new("/sysroot/home/user/.local/share/applications"),
+                Path::new("/sysroot/usr/share/applications"),
+            ]
+        );
+        let lists: Vec<_> = dirs.mimeapps_lists().collect();
+        assert_eq!(
+            lists,
+            [
+                Path::new("/sysroot/home/user/.config/mimeapps.list"),
+                Path::new("/sysroot/etc/xdg/mimeapps.list"),
+                Path::new("/sysroot/home/user/.local/share/applications/mimeapps.list"),
+                Path::new("/sysroot/usr/share/applications/mimeapps.list"),
+            ]
+        );
+    }
+}
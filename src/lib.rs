@@ -1,3 +1,30 @@
+//! Parse and execute freedesktop.org desktop entry files.
+//!
+//! None of the parsing functions panic on malformed input: invalid syntax,
+//! invalid UTF-8 and IO failures are all reported as a `ParseError`. The
+//! only panicking API is indexing a `DesktopEntry` by group name, which has
+//! the non-panicking equivalent `DesktopEntry::group`.
+//!
+//! Nothing is printed by the library. With the `tracing` feature, parse
+//! progress and warnings are reported as `tracing` events instead, so they
+//! only show up if the consumer installs a subscriber.
+//!
+//! The `prelude` module is the supported surface of the crate. Helper
+//! modules and lower-level APIs that may change between minor versions are
+//! hidden from the documentation unless the `unstable` feature is enabled.
+//! These are `uri`, `mimecache`, borrowed parsing with `parse_borrowed`,
+//! lossless editing with `DesktopDocument`, `EntryDiff` and the
+//! `EntryRegistry` of key descriptors.
+
+/// Emit a `tracing` event at the given level if the feature is enabled
+macro_rules! diagnostic {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+    };
+}
+
 mod desktop;
+pub mod prelude;
 
 pub use desktop::*;
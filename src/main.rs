@@ -1,19 +0,0 @@
-// extern crate clap;
-#[macro_use]
-extern crate nom;
-// we shouldn't really need these
-#[macro_use]
-extern crate lazy_static;
-extern crate regex;
-
-#[allow(dead_code)]
-mod desktop;
-
-use desktop::*;
-use std::env;
-
-fn main() {
-    let path = env::args().nth(1).unwrap();
-    let result = parse_file(path).unwrap();
-    println!("Parsed: {:?}", result);
-}
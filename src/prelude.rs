@@ -0,0 +1,16 @@
+//! The stable API of the crate
+//!
+//! Everything exported here follows semver. Use it with
+//! `use libdopen::prelude::*;`.
+
+pub use crate::entries::{
+    Actions, Categories, Category, Comment, DBusActivatable, Entry, Exec, Flatpak, GenericName,
+    GnomeAutostartDelay, Hidden, Icon, Implements, Keywords, MimePattern, MimeType, Name,
+    NoDisplay, NotShowIn, OnlyShowIn, Path, PrefersNonDefaultGPU, SerializeEntry, SingleMainWindow,
+    StartupNotify, StartupWMClass, Terminal, TryExec, Type, Version, URL,
+};
+pub use crate::execute::{execute, EntrySource, ExecContext, Launcher, Launchers};
+pub use crate::{
+    parse, parse_file, Action, DesktopEntry, DesktopEntryBuilder, Error, Group, ParseError,
+    ParseOptions,
+};